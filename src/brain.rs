@@ -17,47 +17,249 @@
  * and navigate to version 3 of the GNU General Public License.
  */
 
+pub mod commands;
+pub mod conversation;
+pub mod dictionary;
+pub mod quality;
+pub mod tokenizer;
+
 use std::borrow::Cow;
-use std::collections::HashSet;
-use once_cell::sync::OnceCell;
+use std::sync::Arc;
+use eyre::Result;
+use crate::database::UserIdentifier;
+use crate::faq::Faq;
+use self::commands::{Commands, Permission};
+use self::conversation::Conversation;
+use self::dictionary::Dictionaries;
+use self::quality::QualityScorer;
+use self::tokenizer::TokenCounts;
 
-// All valid English words
-// This is a rough measure. It isn't exact and doesn't include many American spellings
-static ALL_WORDS_INDEX: OnceCell<HashSet<&'static str>> = OnceCell::new();
+/// A message received on any platform
+#[derive(Debug)]
+pub struct IncomingMessage<'m> {
+    pub content: &'m str,
+    pub sender: UserIdentifier<'m>,
+    pub sender_name: &'m str,
+    /// Where the message was sent, as the channel should be written in a response,
+    /// or `None` for a private message
+    pub channel: Option<&'m str>,
+    /// The names by which the bot may be addressed, including any mentions of it
+    pub own_names: &'m [String],
+    pub permission: Permission,
+    /// Whether the message was sent directly to the bot rather than in a channel
+    pub is_private: bool,
+    /// The services account to which an IRC sender is identified, if any
+    pub irc_account: Option<&'m str>
+}
 
-fn create_all_words_index() -> HashSet<&'static str> {
-    // Words from http://www.mieliestronk.com/wordlist.html
-    let all_words = include_str!("corncob_lowercase.txt");
-    let mut all_words = all_words.split_terminator('\n').collect::<HashSet<_>>();
-    all_words.shrink_to_fit();
-    all_words
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Response {
+    pub content: Cow<'static, str>,
+    /// Structured content shown after the content
+    pub embed: Option<Embed>,
+    /// Whether only the sender should see the response
+    pub private: bool
 }
 
-pub fn count_words<C: AsRef<str>>(content: C) -> u32 {
-    let all_words_index = ALL_WORDS_INDEX.get_or_init(create_all_words_index);
+impl Response {
+    pub fn public<C: Into<Cow<'static, str>>>(content: C) -> Self {
+        Self {
+            content: content.into(),
+            embed: None,
+            private: false
+        }
+    }
+
+    pub fn private<C: Into<Cow<'static, str>>>(content: C) -> Self {
+        Self {
+            content: content.into(),
+            embed: None,
+            private: true
+        }
+    }
 
-    let mut word_count = 0;
-    let content = content.as_ref();
-    for word in content.split(' ') {
-        let word = word.to_lowercase();
-        if all_words_index.contains(word.as_str()) {
-            word_count += 1;
+    pub fn public_embed(embed: Embed) -> Self {
+        Self {
+            embed: Some(embed),
+            ..Self::public("")
         }
     }
-    word_count
+
+    /// The response as lines of text, for platforms which cannot show embeds
+    pub fn lines(&self) -> Vec<String> {
+        let mut lines = self.content.lines().map(String::from).collect::<Vec<_>>();
+        if let Some(embed) = &self.embed {
+            lines.extend(embed.lines());
+        }
+        lines
+    }
 }
 
-pub fn respond_to_message<C: AsRef<str>>(_content: C) -> Option<Cow<'static, str>> {
-    // Todo: Add responses to direct address, inquiries, etc.
-    None
+/// Structured content, which discord shows as an embed. Elsewhere, it is shown as lines
+/// of text using IRC formatting codes
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Embed {
+    pub title: String,
+    /// Names and values, each shown on its own line
+    pub fields: Vec<(String, String)>,
+    /// The colour of the embed's border, as RGB
+    pub colour: Option<u32>,
+    pub footer: Option<String>
+}
+
+impl Embed {
+    const BOLD: char = '\x02';
+    const ITALIC: char = '\x1D';
+
+    pub fn new<T: Into<String>>(title: T) -> Self {
+        Self {
+            title: title.into(),
+            ..Self::default()
+        }
+    }
+
+    pub fn field<N: Into<String>, V: Into<String>>(mut self, name: N, value: V) -> Self {
+        self.fields.push((name.into(), value.into()));
+        self
+    }
+
+    pub fn colour(mut self, colour: u32) -> Self {
+        self.colour = Some(colour);
+        self
+    }
+
+    pub fn footer<F: Into<String>>(mut self, footer: F) -> Self {
+        self.footer = Some(footer.into());
+        self
+    }
+
+    /// The title in bold, each field with its name in bold, and the footer in italics
+    pub fn lines(&self) -> Vec<String> {
+        let mut lines = vec![format!("{bold}{}{bold}", self.title, bold = Self::BOLD)];
+        for (name, value) in &self.fields {
+            lines.push(format!("{bold}{}{bold}: {}", name, value, bold = Self::BOLD));
+        }
+        if let Some(footer) = &self.footer {
+            lines.push(format!("{italic}{}{italic}", footer, italic = Self::ITALIC));
+        }
+        lines
+    }
+}
+
+/// The tokens in a message which is about to be recorded, and its score
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Assessment {
+    pub tokens: TokenCounts,
+    pub score: u32
+}
+
+/// Decides how to respond to messages on every platform
+#[derive(Debug)]
+pub struct Brain {
+    commands: Commands,
+    quality_scorer: QualityScorer,
+    dictionaries: Arc<Dictionaries>,
+    conversation: Conversation,
+    faq: Arc<Faq>
+}
+
+impl Brain {
+    pub fn new(commands: Commands,
+               quality_scorer: QualityScorer,
+               dictionaries: Arc<Dictionaries>,
+               conversation: Conversation,
+               faq: Arc<Faq>) -> Self {
+        Self {
+            commands,
+            quality_scorer,
+            dictionaries,
+            conversation,
+            faq
+        }
+    }
+
+    /// Counts the tokens in a message which is about to be recorded and scores its quality,
+    /// using the language of the channel, or `None` for a private message
+    pub fn assess_message(&self, sender: UserIdentifier<'_>, channel: Option<&str>, content: &str) -> Assessment {
+        let lexicon = self.dictionaries.lexicon();
+        let vocabulary = lexicon.vocabulary(channel);
+        Assessment {
+            tokens: TokenCounts::of(content, |word| vocabulary.contains(word)),
+            score: self.quality_scorer.score(sender, content, vocabulary, crate::current_unix_time())
+        }
+    }
+
+    pub async fn respond_to_message(&self, message: &IncomingMessage<'_>) -> Option<Response> {
+        match self.commands.dispatch(message).await {
+            Some(result) => Some(Self::command_response(message, result)),
+            None => {
+                let response = match self.conversation.respond(message).await {
+                    Ok(None) => self.faq.respond(message).await,
+                    result => result
+                };
+                response.unwrap_or_else(|e| {
+                    log::error!("Failed to answer {:?} from {}: {}", message.content, message.sender_name, e);
+                    None
+                })
+            }
+        }
+    }
+
+    /// Executes a command invoked other than by message, such as through a discord slash command.
+    /// Returns `None` if there is no such command
+    pub async fn execute_command(&self, name: &str, message: &IncomingMessage<'_>,
+                                 arguments: &[String]) -> Option<Response> {
+        let result = self.commands.execute(name, message, arguments).await?;
+        Some(Self::command_response(message, result))
+    }
+
+    fn command_response(message: &IncomingMessage<'_>, result: Result<Response>) -> Response {
+        result.unwrap_or_else(|e| {
+            log::error!("Failed to execute command {:?} from {}: {}", message.content, message.sender_name, e);
+            Response::private("Something went wrong while executing that command")
+        })
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use super::dictionary::Lexicon;
+
+    fn count_tokens(content: &str) -> TokenCounts {
+        let lexicon = Lexicon::built_in();
+        let vocabulary = lexicon.vocabulary(None);
+        TokenCounts::of(content, |word| vocabulary.contains(word))
+    }
 
     #[test]
     fn count_legal_words() {
-        assert_eq!(8, count_words("This is a legal word but this is not : ohuhasiudnakj"))
+        assert_eq!(8, count_tokens("This is a legal word but this is not : ohuhasiudnakj").dictionary_words)
+    }
+
+    #[test]
+    fn count_words_despite_punctuation() {
+        let counts = count_tokens("Hello, world! Don't miss https://example.com <@1234>");
+        assert_eq!(4, counts.dictionary_words);
+        assert_eq!(1, counts.urls);
+        assert_eq!(1, counts.mentions);
+    }
+
+    #[test]
+    fn embeds_as_lines() {
+        let embed = Embed::new("Stats for A248")
+            .field("Messages in total", "12")
+            .colour(0x2ECC71)
+            .footer("The cycle ends in 3 days");
+        let response = Response {
+            embed: Some(embed),
+            ..Response::public("Here you go")
+        };
+        assert_eq!(vec![
+            "Here you go",
+            "\x02Stats for A248\x02",
+            "\x02Messages in total\x02: 12",
+            "\x1DThe cycle ends in 3 days\x1D"
+        ], response.lines());
     }
 }
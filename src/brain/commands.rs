@@ -0,0 +1,285 @@
+/*
+ * faithful-servant-bot
+ * Copyright © 2022 Anand Beh
+ *
+ * faithful-servant-bot is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * faithful-servant-bot is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with faithful-servant-bot. If not, see <https://www.gnu.org/licenses/>
+ * and navigate to version 3 of the GNU General Public License.
+ */
+
+use std::collections::BTreeMap;
+use std::fmt::{self, Display, Formatter};
+use async_trait::async_trait;
+use eyre::Result;
+use crate::database::UserIdentifier;
+use super::{IncomingMessage, Response};
+
+/// Who may use a command. Each level includes the levels beneath it
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Permission {
+    Member,
+    Moderator,
+    Owner
+}
+
+/// A command which is written once and runs on every platform
+#[async_trait]
+pub trait Command: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    /// The arguments accepted, such as `<user> [reason]`
+    fn usage(&self) -> &'static str {
+        ""
+    }
+
+    fn help(&self) -> &'static str;
+
+    fn permission(&self) -> Permission {
+        Permission::Member
+    }
+
+    async fn execute(&self, message: &IncomingMessage<'_>, arguments: &[String]) -> Result<Response>;
+}
+
+/// The command registry
+pub struct Commands {
+    prefix: String,
+    commands: BTreeMap<&'static str, Box<dyn Command>>
+}
+
+impl fmt::Debug for Commands {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Commands")
+            .field("prefix", &self.prefix)
+            .field("commands", &self.commands.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+impl Commands {
+    pub fn new(prefix: String) -> Self {
+        Self {
+            prefix,
+            commands: BTreeMap::new()
+        }
+    }
+
+    pub fn register<C: Command + 'static>(&mut self, command: C) {
+        let name = command.name();
+        if self.commands.insert(name, Box::new(command)).is_some() {
+            panic!("Command {} registered twice", name);
+        }
+    }
+
+    /// Executes the command contained in the message, if it contains one.
+    /// In private messages, the prefix may be omitted
+    pub async fn dispatch(&self, message: &IncomingMessage<'_>) -> Option<Result<Response>> {
+        let content = message.content.trim();
+        let command_line = match content.strip_prefix(self.prefix.as_str()) {
+            Some(command_line) => command_line,
+            None if message.is_private => content,
+            None => return None
+        };
+        // Only a message naming a command has its arguments parsed, so that anything else,
+        // however it is quoted, is left for conversation
+        let name = command_line.split_whitespace().next()?.to_lowercase();
+        if name != "help" && !self.commands.contains_key(name.as_str()) {
+            return None;
+        }
+        let mut arguments = match parse_arguments(command_line) {
+            Ok(arguments) => arguments,
+            Err(e) => return Some(Ok(Response::private(format!("Invalid arguments: {}", e))))
+        };
+        if arguments.is_empty() {
+            return None;
+        }
+        let name = arguments.remove(0).to_lowercase();
+        self.execute(&name, message, &arguments).await
+    }
+
+    /// Executes the named command with arguments which are already parsed.
+    /// Returns `None` if there is no such command
+    pub async fn execute(&self, name: &str, message: &IncomingMessage<'_>,
+                         arguments: &[String]) -> Option<Result<Response>> {
+        if name == "help" {
+            return Some(Ok(self.help(message, arguments)));
+        }
+        // Unknown commands are ignored, since they may be meant for another bot
+        let command = self.commands.get(name)?;
+        if message.permission < command.permission() {
+            return Some(Ok(Response::private("You do not have permission to use this command")));
+        }
+        Some(command.execute(message, arguments).await)
+    }
+
+    fn help(&self, message: &IncomingMessage<'_>, arguments: &[String]) -> Response {
+        match arguments.first() {
+            Some(name) => match self.commands.get(name.to_lowercase().as_str()) {
+                Some(command) => Response::private(format!(
+                    "{}{} {} - {}", self.prefix, command.name(), command.usage(), command.help()
+                )),
+                None => Response::private(format!("Unknown command: {}", name))
+            },
+            None => {
+                let available = self.commands.values()
+                    .filter(|command| message.permission >= command.permission())
+                    .map(|command| format!("{}{}", self.prefix, command.name()))
+                    .collect::<Vec<_>>();
+                Response::private(format!(
+                    "Commands: {}. Use {}help <command> for details",
+                    available.join(", "), self.prefix
+                ))
+            }
+        }
+    }
+}
+
+/// Parses a user given as a command argument. Discord mentions and IDs refer to discord users;
+/// anything else is an IRC nickname
+pub fn parse_user(argument: &str) -> UserIdentifier<'_> {
+    let discord_id = argument.strip_prefix("<@")
+        .and_then(|mention| mention.strip_suffix('>'))
+        .map(|mention| mention.trim_start_matches('!'))
+        .unwrap_or(argument);
+    match discord_id.parse() {
+        Ok(discord_id) => UserIdentifier::DiscordId(discord_id),
+        Err(_) => UserIdentifier::IrcNickname(argument)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum ArgumentError {
+    UnclosedQuote,
+    TrailingEscape
+}
+
+impl Display for ArgumentError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnclosedQuote => f.write_str("unclosed quotation mark"),
+            Self::TrailingEscape => f.write_str("nothing follows the final backslash")
+        }
+    }
+}
+
+impl std::error::Error for ArgumentError {}
+
+/// Splits a command line on whitespace. Double quotes group words into one argument,
+/// and a backslash escapes the character following it
+pub fn parse_arguments(command_line: &str) -> Result<Vec<String>, ArgumentError> {
+    let mut arguments = Vec::new();
+    let mut current: Option<String> = None;
+    let mut in_quotes = false;
+    let mut chars = command_line.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                let escaped = chars.next().ok_or(ArgumentError::TrailingEscape)?;
+                current.get_or_insert_with(String::new).push(escaped);
+            }
+            '"' => {
+                in_quotes = !in_quotes;
+                // Ensure "" yields an empty argument
+                current.get_or_insert_with(String::new);
+            }
+            c if c.is_whitespace() && !in_quotes => {
+                arguments.extend(current.take());
+            }
+            c => current.get_or_insert_with(String::new).push(c)
+        }
+    }
+    if in_quotes {
+        return Err(ArgumentError::UnclosedQuote);
+    }
+    arguments.extend(current);
+    Ok(arguments)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct EchoCommand;
+
+    #[async_trait]
+    impl Command for EchoCommand {
+        fn name(&self) -> &'static str {
+            "echo"
+        }
+
+        fn help(&self) -> &'static str {
+            "Repeats the arguments"
+        }
+
+        async fn execute(&self, _message: &IncomingMessage<'_>, arguments: &[String]) -> Result<Response> {
+            Ok(Response::private(arguments.join(" ")))
+        }
+    }
+
+    async fn dispatch(commands: &Commands, content: &str) -> Result<Option<Response>> {
+        let message = IncomingMessage {
+            content,
+            sender: UserIdentifier::IrcNickname("A248"),
+            sender_name: "A248",
+            channel: None,
+            own_names: &[],
+            permission: Permission::Member,
+            is_private: true,
+            irc_account: None
+        };
+        commands.dispatch(&message).await.transpose()
+    }
+
+    #[async_std::test]
+    async fn report_malformed_arguments_only_to_commands() -> Result<()> {
+        let mut commands = Commands::new(String::from("!"));
+        commands.register(EchoCommand);
+
+        assert_eq!(Some(Response::private("a b")), dispatch(&commands, "echo \"a b\"").await?);
+        assert_eq!(
+            Some(Response::private("Invalid arguments: unclosed quotation mark")),
+            dispatch(&commands, "!echo \"unclosed").await?
+        );
+        // Not a command, so it is left for conversation
+        assert_eq!(None, dispatch(&commands, "it's called \"the bot").await?);
+        assert_eq!(None, dispatch(&commands, "\"echo").await?);
+        Ok(())
+    }
+
+    #[test]
+    fn split_on_whitespace() {
+        assert_eq!(Ok(vec!["stats".to_string(), "week".to_string()]), parse_arguments(" stats   week "));
+    }
+
+    #[test]
+    fn quotes_and_escapes() {
+        assert_eq!(
+            Ok(vec!["faq".to_string(), "how do I join".to_string(), "say \"hi\"".to_string(), String::new()]),
+            parse_arguments(r#"faq "how do I join" say\ \"hi\" """#)
+        );
+    }
+
+    #[test]
+    fn malformed_arguments() {
+        assert_eq!(Err(ArgumentError::UnclosedQuote), parse_arguments("faq \"unclosed"));
+        assert_eq!(Err(ArgumentError::TrailingEscape), parse_arguments("faq \\"));
+    }
+
+    #[test]
+    fn parse_users() {
+        assert_eq!(UserIdentifier::DiscordId(1234), parse_user("<@!1234>"));
+        assert_eq!(UserIdentifier::DiscordId(1234), parse_user("<@1234>"));
+        assert_eq!(UserIdentifier::DiscordId(1234), parse_user("1234"));
+        assert_eq!(UserIdentifier::IrcNickname("A248"), parse_user("A248"));
+    }
+}
@@ -0,0 +1,277 @@
+/*
+ * faithful-servant-bot
+ * Copyright © 2022 Anand Beh
+ *
+ * faithful-servant-bot is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * faithful-servant-bot is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with faithful-servant-bot. If not, see <https://www.gnu.org/licenses/>
+ * and navigate to version 3 of the GNU General Public License.
+ */
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use eyre::Result;
+use rand::seq::SliceRandom;
+use crate::config;
+use crate::database::Database;
+use super::{IncomingMessage, Response};
+use super::quality::Sender;
+
+/// Beyond this many entries, expired cooldowns are discarded
+const PRUNE_THRESHOLD: usize = 1024;
+
+#[derive(Debug)]
+struct Rule {
+    /// Normalized with `normalize`
+    patterns: Vec<String>,
+    responses: Vec<String>
+}
+
+impl Rule {
+    fn matches(&self, text: &str) -> bool {
+        let text = format!(" {} ", text);
+        self.patterns.iter().any(|pattern| pattern == "*" || text.contains(&format!(" {} ", pattern)))
+    }
+}
+
+/// When the bot last answered each user and in each channel
+#[derive(Debug, Default)]
+struct Cooldowns {
+    users: HashMap<Sender, u64>,
+    channels: HashMap<String, u64>
+}
+
+impl Cooldowns {
+    /// Whether the bot may answer now, remembering the answer if so
+    fn try_answer(&mut self, config: &config::Conversation,
+                  sender: Sender, channel: Option<&str>, now: u64) -> bool {
+        let user_waiting = matches!(
+            self.users.get(&sender), Some(answered) if answered + config.user_cooldown_seconds > now
+        );
+        let channel_waiting = matches!(
+            channel.and_then(|channel| self.channels.get(channel)),
+            Some(answered) if answered + config.channel_cooldown_seconds > now
+        );
+        if user_waiting || channel_waiting {
+            return false;
+        }
+        if self.users.len() > PRUNE_THRESHOLD {
+            self.users.retain(|_, answered| *answered + config.user_cooldown_seconds > now);
+        }
+        if self.channels.len() > PRUNE_THRESHOLD {
+            self.channels.retain(|_, answered| *answered + config.channel_cooldown_seconds > now);
+        }
+        self.users.insert(sender, now);
+        if let Some(channel) = channel {
+            self.channels.insert(channel.to_string(), now);
+        }
+        true
+    }
+}
+
+/// Answers messages addressed to the bot according to the configured rules
+#[derive(Debug)]
+pub struct Conversation {
+    config: config::Conversation,
+    rules: Vec<Rule>,
+    database: Database,
+    cooldowns: Mutex<Cooldowns>
+}
+
+impl Conversation {
+    pub fn new(config: config::Conversation, database: Database) -> Self {
+        let rules = config.rules.iter()
+            .filter(|rule| !rule.responses.is_empty())
+            .map(|rule| Rule {
+                patterns: rule.patterns.iter().map(|pattern| normalize(pattern)).collect(),
+                responses: rule.responses.clone()
+            })
+            .collect();
+        Self {
+            config,
+            rules,
+            database,
+            cooldowns: Mutex::default()
+        }
+    }
+
+    /// Answers the message if it is addressed to the bot, matches a rule, and no cooldown applies.
+    /// Private messages are always addressed to the bot
+    pub async fn respond(&self, message: &IncomingMessage<'_>) -> Result<Option<Response>> {
+        let content = match strip_address(message.content, message.own_names) {
+            Some(content) => content,
+            None if message.is_private => message.content,
+            None => return Ok(None)
+        };
+        let text = normalize(content);
+        let template = self.rules.iter()
+            .find(|rule| rule.matches(&text))
+            .and_then(|rule| rule.responses.choose(&mut rand::thread_rng()));
+        let template = match template {
+            Some(template) => template,
+            None => return Ok(None)
+        };
+        let may_answer = self.cooldowns.lock().unwrap().try_answer(
+            &self.config, Sender::from(message.sender), message.channel, crate::current_unix_time()
+        );
+        if !may_answer {
+            log::debug!("Not answering {} during the cooldown", message.sender_name);
+            return Ok(None);
+        }
+        Ok(Some(Response::public(self.fill_placeholders(template, message).await?)))
+    }
+
+    async fn fill_placeholders(&self, template: &str, message: &IncomingMessage<'_>) -> Result<String> {
+        // The sender's name is filled last, so that names resembling placeholders are left alone
+        let mut filled = template.to_string();
+        if filled.contains("{messages}") {
+            let messages = match self.database.find_user(message.sender).await? {
+                Some(user_id) => self.database.count_messages(user_id, 0, 0).await?,
+                None => 0
+            };
+            filled = filled.replace("{messages}", &messages.to_string());
+        }
+        if filled.contains("{inducted}") {
+            let inducted = self.database.is_inducted(message.sender).await?;
+            filled = filled.replace("{inducted}", if inducted { "inducted" } else { "not inducted" });
+        }
+        Ok(filled
+            .replace("{channel}", message.channel.unwrap_or("our private conversation"))
+            .replace("{user}", message.sender_name))
+    }
+}
+
+/// If the content begins or ends with one of the names, yields the remainder
+fn strip_address<'c>(content: &'c str, names: &[String]) -> Option<&'c str> {
+    let content = content.trim();
+    names.iter()
+        .filter(|name| !name.is_empty())
+        .find_map(|name| {
+            let unprefixed = content.strip_prefix('@').unwrap_or(content);
+            let unsuffixed = content.trim_end_matches(&['.', '!', '?'][..]);
+            strip_leading_name(unprefixed, name).or_else(|| strip_trailing_name(unsuffixed, name))
+        })
+}
+
+/// Such as "name: hello" or "name, hello"
+fn strip_leading_name<'c>(content: &'c str, name: &str) -> Option<&'c str> {
+    let is_separator = |c: char| c.is_whitespace() || matches!(c, ':' | ',' | '!' | '?');
+    let head = content.get(..name.len())?;
+    let rest = &content[name.len()..];
+    if !head.eq_ignore_ascii_case(name) {
+        return None;
+    }
+    match rest.chars().next() {
+        None => Some(rest),
+        Some(c) if is_separator(c) => Some(rest.trim_start_matches(is_separator)),
+        Some(_) => None
+    }
+}
+
+/// Such as "thanks, name" or "hello @name"
+fn strip_trailing_name<'c>(content: &'c str, name: &str) -> Option<&'c str> {
+    let is_separator = |c: char| c.is_whitespace() || c == ',';
+    let split = content.len().checked_sub(name.len())?;
+    let tail = content.get(split..)?;
+    if !tail.eq_ignore_ascii_case(name) {
+        return None;
+    }
+    let rest = &content[..split];
+    let rest = rest.strip_suffix('@').unwrap_or(rest);
+    match rest.chars().next_back() {
+        Some(c) if is_separator(c) => Some(rest.trim_end_matches(is_separator)),
+        _ => None
+    }
+}
+
+/// Lowercases the text and replaces punctuation with spaces, so that patterns match whole words
+fn normalize(text: &str) -> String {
+    let text = text.to_lowercase()
+        .chars()
+        .map(|c| if c.is_alphanumeric() || c == '\'' || c == '*' { c } else { ' ' })
+        .collect::<String>();
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::brain::commands::Permission;
+    use crate::database::{test_support, RecordedMessage, UserIdentifier};
+    use crate::brain::Assessment;
+    use crate::brain::tokenizer::TokenCounts;
+    use super::*;
+
+    fn names() -> Vec<String> {
+        vec![String::from("Servant"), String::from("<@1234>")]
+    }
+
+    fn message<'m>(content: &'m str, own_names: &'m [String]) -> IncomingMessage<'m> {
+        IncomingMessage {
+            content,
+            sender: UserIdentifier::IrcNickname("A248"),
+            sender_name: "A248",
+            channel: Some("#chat"),
+            own_names,
+            permission: Permission::Member,
+            is_private: false,
+            irc_account: None
+        }
+    }
+
+    #[test]
+    fn detect_direct_address() {
+        let names = names();
+        assert_eq!(Some("hello there"), strip_address("Servant: hello there", &names));
+        assert_eq!(Some("hello"), strip_address("@servant, hello", &names));
+        assert_eq!(Some("hi"), strip_address("<@1234> hi", &names));
+        assert_eq!(Some("thanks"), strip_address("thanks, Servant!", &names));
+        assert_eq!(Some(""), strip_address("Servant?", &names));
+        assert_eq!(None, strip_address("Servants are hard to find", &names));
+        assert_eq!(None, strip_address("hello everyone", &names));
+    }
+
+    #[test]
+    fn patterns_match_whole_words() {
+        let rule = Rule {
+            patterns: vec![normalize("How are you?"), normalize("hi")],
+            responses: vec![]
+        };
+        assert!(rule.matches(&normalize("Hey, how are you doing?")));
+        assert!(rule.matches(&normalize("hi!")));
+        assert!(!rule.matches(&normalize("this is fine")));
+    }
+
+    #[async_std::test]
+    async fn answer_with_placeholders_and_cooldowns() -> Result<()> {
+        let tempdir = tempfile::tempdir()?;
+        let database = test_support::sqlite_in(&tempdir).await?;
+        database.migrate().await?;
+        let assessment = Assessment { tokens: TokenCounts::default(), score: 3 };
+        database.record_message(&RecordedMessage::new(UserIdentifier::IrcNickname("A248"), "one two three", assessment)).await?;
+
+        let conversation = Conversation::new(config::Conversation {
+            rules: vec![config::ConversationRule {
+                patterns: vec![String::from("stats")],
+                responses: vec![String::from("{user}: {messages} messages in {channel}")]
+            }],
+            ..config::Conversation::default()
+        }, database);
+        let names = names();
+        assert_eq!(None, conversation.respond(&message("stats", &names)).await?);
+        assert_eq!(
+            Some(Response::public("A248: 1 messages in #chat")),
+            conversation.respond(&message("Servant: stats", &names)).await?
+        );
+        assert_eq!(None, conversation.respond(&message("Servant: stats", &names)).await?);
+        Ok(())
+    }
+}
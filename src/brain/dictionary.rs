@@ -0,0 +1,299 @@
+/*
+ * faithful-servant-bot
+ * Copyright © 2022 Anand Beh
+ *
+ * faithful-servant-bot is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * faithful-servant-bot is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with faithful-servant-bot. If not, see <https://www.gnu.org/licenses/>
+ * and navigate to version 3 of the GNU General Public License.
+ */
+
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, RwLock};
+use async_std::fs;
+use async_trait::async_trait;
+use eyre::{Result, WrapErr};
+use once_cell::sync::OnceCell;
+use crate::config;
+use super::{IncomingMessage, Response};
+use super::commands::{Command, Permission};
+
+/// The language which always includes the built-in English dictionary
+pub const BUILT_IN_LANGUAGE: &str = "english";
+
+// Valid English words
+// This is a rough measure. It isn't exact and doesn't include many American spellings,
+// which may be added through further dictionary files
+static BUILT_IN_WORDS: OnceCell<HashSet<&'static str>> = OnceCell::new();
+
+fn create_built_in_words() -> HashSet<&'static str> {
+    // Words from http://www.mieliestronk.com/wordlist.html
+    let all_words = include_str!("../corncob_lowercase.txt");
+    let mut all_words = all_words.split_terminator('\n').collect::<HashSet<_>>();
+    all_words.shrink_to_fit();
+    all_words
+}
+
+/// The words of one language
+#[derive(Debug, Default)]
+struct Language {
+    built_in: bool,
+    words: HashSet<String>
+}
+
+impl Language {
+    fn contains(&self, word: &str) -> bool {
+        (self.built_in && BUILT_IN_WORDS.get_or_init(create_built_in_words).contains(word))
+            || self.words.contains(word)
+    }
+
+    fn len(&self) -> usize {
+        let built_in = if self.built_in { BUILT_IN_WORDS.get_or_init(create_built_in_words).len() } else { 0 };
+        built_in + self.words.len()
+    }
+}
+
+/// Every configured language, along with the words allowed or denied in all of them
+#[derive(Debug)]
+pub struct Lexicon {
+    languages: HashMap<String, Language>,
+    default_language: String,
+    /// Keyed by lowercase channel, since IRC channel names are case insensitive
+    channel_languages: HashMap<String, String>,
+    allowed_words: HashSet<String>,
+    denied_words: HashSet<String>
+}
+
+impl Lexicon {
+    /// The built-in English dictionary alone
+    pub fn built_in() -> Self {
+        let mut languages = HashMap::new();
+        languages.insert(BUILT_IN_LANGUAGE.to_string(), Language { built_in: true, words: HashSet::new() });
+        Self {
+            languages,
+            default_language: BUILT_IN_LANGUAGE.to_string(),
+            channel_languages: HashMap::new(),
+            allowed_words: HashSet::new(),
+            denied_words: HashSet::new()
+        }
+    }
+
+    /// Reads every dictionary file named in the configuration
+    pub async fn load(config: &config::Dictionaries) -> Result<Self> {
+        let mut lexicon = Self::built_in();
+        for (name, files) in &config.languages {
+            let language = lexicon.languages.entry(name.to_lowercase()).or_default();
+            for file in files {
+                let contents = fs::read_to_string(file).await
+                    .wrap_err_with(|| format!("Failed to read dictionary file {} for {}", file, name))?;
+                language.words.extend(parse_words(&contents));
+            }
+        }
+        if let Some(default_language) = &config.default_language {
+            lexicon.default_language = lexicon.known_language(default_language)?;
+        }
+        for (channel, language) in &config.channel_languages {
+            let language = lexicon.known_language(language)?;
+            lexicon.channel_languages.insert(channel.to_lowercase(), language);
+        }
+        lexicon.allowed_words = parse_words(&config.allowed_words.join("\n")).collect();
+        lexicon.denied_words = parse_words(&config.denied_words.join("\n")).collect();
+        Ok(lexicon)
+    }
+
+    fn known_language(&self, language: &str) -> Result<String> {
+        let language = language.to_lowercase();
+        if !self.languages.contains_key(&language) {
+            eyre::bail!("The language {} has no dictionary files", language);
+        }
+        Ok(language)
+    }
+
+    /// The words counted in the given channel, which is an IRC channel or discord channel ID.
+    /// Private messages use the default language
+    pub fn vocabulary(&self, channel: Option<&str>) -> Vocabulary<'_> {
+        let language = channel
+            .and_then(|channel| self.channel_languages.get(&channel.to_lowercase()))
+            .unwrap_or(&self.default_language);
+        Vocabulary {
+            lexicon: self,
+            language: &self.languages[language]
+        }
+    }
+
+    fn describe(&self) -> String {
+        let mut languages = self.languages.iter()
+            .map(|(name, language)| format!("{} ({} words)", name, language.len()))
+            .collect::<Vec<_>>();
+        languages.sort();
+        format!(
+            "Languages: {}. Default language: {}. {} allowed and {} denied words",
+            languages.join(", "), self.default_language, self.allowed_words.len(), self.denied_words.len()
+        )
+    }
+}
+
+/// One language, together with the allowed and denied words
+#[derive(Copy, Clone, Debug)]
+pub struct Vocabulary<'l> {
+    lexicon: &'l Lexicon,
+    language: &'l Language
+}
+
+impl Vocabulary<'_> {
+    /// Whether the word, which must be in lowercase, counts as a dictionary word
+    pub fn contains(&self, word: &str) -> bool {
+        !self.lexicon.denied_words.contains(word)
+            && (self.lexicon.allowed_words.contains(word) || self.language.contains(word))
+    }
+}
+
+/// Dictionary files have one word per line. Blank lines and lines starting with '#' are skipped
+fn parse_words(contents: &str) -> impl Iterator<Item = String> + '_ {
+    contents.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(str::to_lowercase)
+}
+
+/// The dictionaries in use, which may be reloaded without restarting
+#[derive(Debug)]
+pub struct Dictionaries {
+    config: RwLock<config::Dictionaries>,
+    lexicon: RwLock<Arc<Lexicon>>
+}
+
+impl Dictionaries {
+    pub async fn load(config: config::Dictionaries) -> Result<Self> {
+        let lexicon = Lexicon::load(&config).await?;
+        Ok(Self {
+            config: RwLock::new(config),
+            lexicon: RwLock::new(Arc::new(lexicon))
+        })
+    }
+
+    pub fn lexicon(&self) -> Arc<Lexicon> {
+        self.lexicon.read().unwrap().clone()
+    }
+
+    /// Reads the dictionary files again. If any cannot be read, the current dictionaries remain
+    pub async fn reload(&self) -> Result<()> {
+        let config = self.config.read().unwrap().clone();
+        self.reload_with(config).await
+    }
+
+    /// Switches to a new configuration, reading the dictionary files it names. If any cannot be
+    /// read, the current configuration and dictionaries remain
+    pub async fn reload_with(&self, config: config::Dictionaries) -> Result<()> {
+        let lexicon = Lexicon::load(&config).await?;
+        *self.lexicon.write().unwrap() = Arc::new(lexicon);
+        *self.config.write().unwrap() = config;
+        Ok(())
+    }
+}
+
+pub struct DictionariesCommand {
+    dictionaries: Arc<Dictionaries>
+}
+
+impl DictionariesCommand {
+    pub fn new(dictionaries: Arc<Dictionaries>) -> Self {
+        Self {
+            dictionaries
+        }
+    }
+}
+
+#[async_trait]
+impl Command for DictionariesCommand {
+    fn name(&self) -> &'static str {
+        "dictionaries"
+    }
+
+    fn usage(&self) -> &'static str {
+        "[reload]"
+    }
+
+    fn help(&self) -> &'static str {
+        "Shows the dictionaries used to count words, or reads the dictionary files again"
+    }
+
+    fn permission(&self) -> Permission {
+        Permission::Moderator
+    }
+
+    async fn execute(&self, _message: &IncomingMessage<'_>, arguments: &[String]) -> Result<Response> {
+        match arguments.first().map(String::as_str) {
+            None => Ok(Response::private(self.dictionaries.lexicon().describe())),
+            Some("reload") => match self.dictionaries.reload().await {
+                Ok(()) => {
+                    log::info!("Reloaded dictionaries");
+                    Ok(Response::private(format!("Reloaded. {}", self.dictionaries.lexicon().describe())))
+                }
+                Err(e) => {
+                    log::warn!("Failed to reload dictionaries: {:#}", e);
+                    Ok(Response::private(format!("The dictionaries were not reloaded: {}", e)))
+                }
+            },
+            Some(other) => Ok(Response::private(format!("Unknown option: {}", other)))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use super::*;
+
+    #[test]
+    fn allowed_and_denied_words() {
+        let mut lexicon = Lexicon::built_in();
+        lexicon.allowed_words.insert(String::from("irc"));
+        lexicon.denied_words.insert(String::from("the"));
+        let vocabulary = lexicon.vocabulary(None);
+        assert!(vocabulary.contains("irc"));
+        assert!(!vocabulary.contains("the"));
+        assert!(vocabulary.contains("legal"));
+    }
+
+    #[async_std::test]
+    async fn languages_by_channel() -> Result<()> {
+        let tempdir = tempfile::tempdir()?;
+        let spanish = tempdir.path().join("spanish.txt");
+        std::fs::write(&spanish, "# Palabras\nhola\n\nMundo\n")?;
+        let config = config::Dictionaries {
+            languages: BTreeMap::from([(String::from("spanish"), vec![spanish.display().to_string()])]),
+            channel_languages: BTreeMap::from([(String::from("#Espanol"), String::from("Spanish"))]),
+            ..config::Dictionaries::default()
+        };
+        let dictionaries = Dictionaries::load(config).await?;
+        let lexicon = dictionaries.lexicon();
+        assert!(lexicon.vocabulary(Some("#espanol")).contains("mundo"));
+        assert!(!lexicon.vocabulary(Some("#espanol")).contains("world"));
+        assert!(lexicon.vocabulary(Some("#general")).contains("world"));
+        assert!(!lexicon.vocabulary(None).contains("hola"));
+
+        std::fs::write(&spanish, "hola\nmundo\nadios\n")?;
+        dictionaries.reload().await?;
+        assert!(dictionaries.lexicon().vocabulary(Some("#espanol")).contains("adios"));
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn unknown_languages_are_rejected() {
+        let config = config::Dictionaries {
+            default_language: Some(String::from("klingon")),
+            ..config::Dictionaries::default()
+        };
+        assert!(Lexicon::load(&config).await.is_err());
+    }
+}
@@ -0,0 +1,167 @@
+/*
+ * faithful-servant-bot
+ * Copyright © 2022 Anand Beh
+ *
+ * faithful-servant-bot is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * faithful-servant-bot is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with faithful-servant-bot. If not, see <https://www.gnu.org/licenses/>
+ * and navigate to version 3 of the GNU General Public License.
+ */
+
+use std::collections::{HashMap, VecDeque};
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::sync::Mutex;
+use crate::config::Quality;
+use crate::database::UserIdentifier;
+use super::dictionary::Vocabulary;
+use super::tokenizer::{Token, tokenize};
+
+/// Beyond this many senders, the history of inactive senders is discarded
+const PRUNE_THRESHOLD: usize = 1024;
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub(super) enum Sender {
+    Discord(u64),
+    /// IRC nicknames are case insensitive, so they are kept in lowercase
+    Irc(String)
+}
+
+impl From<UserIdentifier<'_>> for Sender {
+    fn from(user_identifier: UserIdentifier<'_>) -> Self {
+        match user_identifier {
+            UserIdentifier::DiscordId(discord_id) => Self::Discord(discord_id),
+            UserIdentifier::IrcNickname(irc_nickname) => Self::Irc(irc_nickname.to_lowercase())
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Sent {
+    time: u64,
+    fingerprint: u64
+}
+
+/// Scores messages by the dictionary words they contain, penalising repeated words,
+/// duplicate messages and flooding
+#[derive(Debug)]
+pub struct QualityScorer {
+    config: Quality,
+    history: Mutex<HashMap<Sender, VecDeque<Sent>>>
+}
+
+impl QualityScorer {
+    pub fn new(config: Quality) -> Self {
+        Self {
+            config,
+            history: Mutex::default()
+        }
+    }
+
+    /// Scores a message sent at the given time, remembering it for scoring later messages
+    pub fn score(&self, sender: UserIdentifier<'_>, content: &str, vocabulary: Vocabulary<'_>, now: u64) -> u32 {
+        let word_score = self.score_words(content, vocabulary);
+        let fingerprint = fingerprint(content);
+        let retention = self.config.duplicate_window_seconds.max(self.config.burst_window_seconds);
+
+        let mut history = self.history.lock().unwrap();
+        if history.len() > PRUNE_THRESHOLD {
+            history.retain(|_, sent| {
+                sent.retain(|sent| sent.time + retention > now);
+                !sent.is_empty()
+            });
+        }
+        let sent = history.entry(Sender::from(sender)).or_default();
+        sent.retain(|sent| sent.time + retention > now);
+
+        let is_duplicate = sent.iter().any(|sent| {
+            sent.fingerprint == fingerprint && sent.time + self.config.duplicate_window_seconds > now
+        });
+        let burst = sent.iter()
+            .filter(|sent| sent.time + self.config.burst_window_seconds > now)
+            .count();
+        sent.push_back(Sent { time: now, fingerprint });
+
+        if is_duplicate || burst >= self.config.burst_limit as usize {
+            0
+        } else {
+            word_score
+        }
+    }
+
+    /// Counts dictionary words, counting each word no more than the permitted number of times
+    fn score_words(&self, content: &str, vocabulary: Vocabulary<'_>) -> u32 {
+        let mut occurrences = HashMap::new();
+        tokenize(content).into_iter()
+            .filter_map(|token| match token {
+                Token::Word(word) if vocabulary.contains(&word) => Some(word),
+                _ => None
+            })
+            .filter(|word| {
+                let occurrence = occurrences.entry(word.clone()).or_insert(0u8);
+                *occurrence = occurrence.saturating_add(1);
+                *occurrence <= self.config.max_word_repeats
+            })
+            .count() as u32
+    }
+}
+
+/// Identifies messages having the same content, ignoring case and spacing
+fn fingerprint(content: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    for word in content.split_whitespace() {
+        word.to_lowercase().hash(&mut hasher);
+    }
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::brain::dictionary::Lexicon;
+
+    const SENDER: UserIdentifier<'static> = UserIdentifier::IrcNickname("A248");
+
+    fn score(scorer: &QualityScorer, sender: UserIdentifier<'_>, content: &str, now: u64) -> u32 {
+        scorer.score(sender, content, Lexicon::built_in().vocabulary(None), now)
+    }
+
+    #[test]
+    fn repeated_words_are_limited() {
+        let scorer = QualityScorer::new(Quality::default());
+        assert_eq!(2, score(&scorer, SENDER, "the the the the", 0));
+        assert_eq!(6, score(&scorer, SENDER, "the cat sat on the mat", 100));
+    }
+
+    #[test]
+    fn duplicates_score_nothing() {
+        let scorer = QualityScorer::new(Quality::default());
+        assert_eq!(3, score(&scorer, SENDER, "this is legal", 0));
+        assert_eq!(0, score(&scorer, SENDER, "This  is LEGAL", 100));
+        // Other users may say the same thing
+        assert_eq!(3, score(&scorer, UserIdentifier::DiscordId(1234), "this is legal", 100));
+        // Once the window passes, repeating oneself is allowed
+        assert_eq!(3, score(&scorer, SENDER, "this is legal", 100 + Quality::default().duplicate_window_seconds));
+    }
+
+    #[test]
+    fn flooding_scores_nothing() {
+        let config = Quality::default();
+        let scorer = QualityScorer::new(config.clone());
+        let messages = ["one", "two", "three", "four", "five", "six", "seven"];
+        let scores = messages.iter()
+            .map(|content| score(&scorer, SENDER, content, 0))
+            .collect::<Vec<_>>();
+        assert_eq!(vec![1, 1, 1, 1, 1, 0, 0], scores);
+        assert_eq!(1, score(&scorer, SENDER, "eight", config.burst_window_seconds));
+    }
+}
@@ -0,0 +1,320 @@
+/*
+ * faithful-servant-bot
+ * Copyright © 2022 Anand Beh
+ *
+ * faithful-servant-bot is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * faithful-servant-bot is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with faithful-servant-bot. If not, see <https://www.gnu.org/licenses/>
+ * and navigate to version 3 of the GNU General Public License.
+ */
+
+/// A piece of message content
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Token<'c> {
+    /// A word in lowercase, with any contraction expanded to its first word
+    Word(String),
+    Url(&'c str),
+    /// A discord mention of a user, role or channel, or `@everyone` and `@here`
+    Mention(&'c str),
+    /// A unicode emoji, discord custom emoji or `:shortcode:`
+    Emoji(&'c str),
+    /// An IRC bold, colour or other formatting code
+    FormattingCode(&'c str),
+    /// A code block or inline code, delimited by backticks
+    Code(&'c str)
+}
+
+/// How many tokens of each kind a message contains
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TokenCounts {
+    /// Words found in the dictionary
+    pub dictionary_words: u32,
+    /// Words not found in the dictionary, including numbers
+    pub other_words: u32,
+    pub urls: u32,
+    pub mentions: u32,
+    pub emoji: u32,
+    pub formatting_codes: u32,
+    pub code_blocks: u32
+}
+
+impl TokenCounts {
+    /// Counts the tokens in the content, checking words against the dictionary
+    pub fn of(content: &str, is_dictionary_word: impl Fn(&str) -> bool) -> Self {
+        let mut counts = Self::default();
+        for token in tokenize(content) {
+            let count = match token {
+                Token::Word(word) if is_dictionary_word(&word) => &mut counts.dictionary_words,
+                Token::Word(_) => &mut counts.other_words,
+                Token::Url(_) => &mut counts.urls,
+                Token::Mention(_) => &mut counts.mentions,
+                Token::Emoji(_) => &mut counts.emoji,
+                Token::FormattingCode(_) => &mut counts.formatting_codes,
+                Token::Code(_) => &mut counts.code_blocks
+            };
+            *count += 1;
+        }
+        counts
+    }
+}
+
+/// Contractions whose first word cannot be found by removing the suffix
+const IRREGULAR_CONTRACTIONS: &[(&str, &str)] = &[("can't", "can"), ("won't", "will"), ("shan't", "shall")];
+const CONTRACTION_SUFFIXES: &[&str] = &["n't", "'s", "'re", "'ve", "'ll", "'d", "'m"];
+
+pub fn tokenize(content: &str) -> Vec<Token<'_>> {
+    let mut tokens = Vec::new();
+    let mut rest = content;
+    while let Some(first) = rest.chars().next() {
+        let (token, length) = match first {
+            '`' => match code(rest) {
+                Some(length) => (Some(Token::Code(&rest[..length])), length),
+                None => (None, 1)
+            },
+            'h' | 'w' if is_url(rest) => {
+                let length = rest.find(char::is_whitespace).unwrap_or(rest.len());
+                // Punctuation directly after a URL usually belongs to the sentence
+                let url = rest[..length].trim_end_matches(&['.', ',', '!', '?', ')', ';', ':'][..]);
+                (Some(Token::Url(url)), length)
+            }
+            '<' => match discord_markup(rest) {
+                Some((token, length)) => (Some(token), length),
+                None => (None, 1)
+            },
+            '@' if rest.starts_with("@everyone") || rest.starts_with("@here") => {
+                let length = if rest.starts_with("@here") { 5 } else { 9 };
+                (Some(Token::Mention(&rest[..length])), length)
+            }
+            ':' => match shortcode(rest) {
+                Some(length) => (Some(Token::Emoji(&rest[..length])), length),
+                None => (None, 1)
+            },
+            c if is_formatting_code(c) => {
+                let length = formatting_code(rest);
+                (Some(Token::FormattingCode(&rest[..length])), length)
+            }
+            c if c.is_alphanumeric() => {
+                let length = word(rest);
+                (Some(Token::Word(expand_contraction(&rest[..length]))), length)
+            }
+            c if is_emoji(c) => {
+                let length = emoji_sequence(rest);
+                (Some(Token::Emoji(&rest[..length])), length)
+            }
+            c => (None, c.len_utf8())
+        };
+        tokens.extend(token);
+        rest = &rest[length..];
+    }
+    tokens
+}
+
+fn is_url(text: &str) -> bool {
+    text.starts_with("https://") || text.starts_with("http://") || text.starts_with("www.")
+}
+
+/// The length of a code block or inline code starting the text, if it is closed
+fn code(text: &str) -> Option<usize> {
+    let delimiter = if text.starts_with("```") { "```" } else { "`" };
+    let inner = &text[delimiter.len()..];
+    inner.find(delimiter).map(|end| delimiter.len() + end + delimiter.len())
+}
+
+/// Parses a mention such as `<@1234>` or a custom emoji such as `<:name:1234>`
+fn discord_markup(text: &str) -> Option<(Token<'_>, usize)> {
+    let end = text.find('>')?;
+    let inner = &text[1..end];
+    let is_id = |id: &str| !id.is_empty() && id.bytes().all(|b| b.is_ascii_digit());
+    let length = end + 1;
+
+    let mention_id = inner.strip_prefix("@!")
+        .or_else(|| inner.strip_prefix("@&"))
+        .or_else(|| inner.strip_prefix('@'))
+        .or_else(|| inner.strip_prefix('#'));
+    if mention_id.is_some_and(is_id) {
+        return Some((Token::Mention(&text[..length]), length));
+    }
+    let emoji = inner.strip_prefix("a:").or_else(|| inner.strip_prefix(':'))?;
+    let (name, id) = emoji.split_once(':')?;
+    if !name.is_empty() && is_id(id) {
+        return Some((Token::Emoji(&text[..length]), length));
+    }
+    None
+}
+
+/// The length of an emoji shortcode such as `:smile:` starting the text
+fn shortcode(text: &str) -> Option<usize> {
+    let inner = &text[1..];
+    let end = inner.find(|c: char| !(c.is_ascii_alphanumeric() || matches!(c, '_' | '+' | '-')))?;
+    if end > 0 && inner[end..].starts_with(':') {
+        Some(end + 2)
+    } else {
+        None
+    }
+}
+
+fn is_formatting_code(c: char) -> bool {
+    matches!(c, '\x02' | '\x03' | '\x04' | '\x0F' | '\x11' | '\x16' | '\x1D' | '\x1E' | '\x1F')
+}
+
+/// The length of the formatting code starting the text, including any colours it sets
+fn formatting_code(text: &str) -> usize {
+    let bytes = text.as_bytes();
+    let (is_colour_digit, max_digits): (fn(&u8) -> bool, usize) = match bytes[0] {
+        b'\x03' => (u8::is_ascii_digit, 2),
+        b'\x04' => (u8::is_ascii_hexdigit, 6),
+        _ => return 1
+    };
+    let digits = |from: usize| {
+        bytes[from..].iter().take(max_digits).take_while(|b| is_colour_digit(b)).count()
+    };
+    let mut length = 1;
+    let foreground = digits(length);
+    length += foreground;
+    if foreground > 0 && bytes.get(length) == Some(&b',') {
+        let background = digits(length + 1);
+        if background > 0 {
+            length += 1 + background;
+        }
+    }
+    length
+}
+
+/// The length of the word starting the text. Apostrophes are kept within words
+fn word(text: &str) -> usize {
+    let mut length = 0;
+    let mut chars = text.char_indices().peekable();
+    while let Some((index, c)) = chars.next() {
+        let continues = c.is_alphanumeric() || (matches!(c, '\'' | '’')
+            && chars.peek().is_some_and(|(_, next)| next.is_alphanumeric()));
+        if !continues {
+            break;
+        }
+        length = index + c.len_utf8();
+    }
+    length
+}
+
+/// Lowercases the word and reduces a contraction, such as "don't", to its first word
+fn expand_contraction(word: &str) -> String {
+    let word = word.to_lowercase().replace('’', "'");
+    if let Some((_, expanded)) = IRREGULAR_CONTRACTIONS.iter().find(|(contraction, _)| *contraction == word) {
+        return expanded.to_string();
+    }
+    CONTRACTION_SUFFIXES.iter()
+        .find_map(|suffix| word.strip_suffix(suffix).filter(|stem| !stem.is_empty()))
+        .map(str::to_string)
+        .unwrap_or(word)
+}
+
+fn is_emoji(c: char) -> bool {
+    matches!(c as u32, 0x1F000..=0x1FAFF | 0x2600..=0x27BF | 0x2B00..=0x2BFF)
+}
+
+/// The length of an emoji and any modifiers or joined emoji following it
+fn emoji_sequence(text: &str) -> usize {
+    let mut length = 0;
+    let mut joined = true;
+    for c in text.chars() {
+        let is_modifier = matches!(c as u32, 0xFE0F | 0x1F3FB..=0x1F3FF | 0x20E3);
+        if is_modifier || (joined && is_emoji(c)) {
+            joined = false;
+        } else if c == '\u{200D}' {
+            joined = true;
+        } else {
+            break;
+        }
+        length += c.len_utf8();
+    }
+    length
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn words(content: &str) -> Vec<String> {
+        tokenize(content).into_iter()
+            .filter_map(|token| match token {
+                Token::Word(word) => Some(word),
+                _ => None
+            })
+            .collect()
+    }
+
+    #[test]
+    fn punctuation_and_contractions() {
+        assert_eq!(vec!["hello", "world", "do", "it", "can"], words("Hello, world! Don't it's... CAN'T"));
+        assert_eq!(vec!["rock", "n", "roll"], words("rock 'n' roll"));
+    }
+
+    #[test]
+    fn discord_markup() {
+        let tokens = tokenize("hi <@!1234> and <#5678>, see <:pog:999> <a:wave:1> :smile: 👋🏽 👨‍👩‍👧 @everyone");
+        assert_eq!(vec![
+            Token::Word(String::from("hi")),
+            Token::Mention("<@!1234>"),
+            Token::Word(String::from("and")),
+            Token::Mention("<#5678>"),
+            Token::Word(String::from("see")),
+            Token::Emoji("<:pog:999>"),
+            Token::Emoji("<a:wave:1>"),
+            Token::Emoji(":smile:"),
+            Token::Emoji("👋🏽"),
+            Token::Emoji("👨‍👩‍👧"),
+            Token::Mention("@everyone")
+        ], tokens);
+    }
+
+    #[test]
+    fn urls_and_code() {
+        let tokens = tokenize("see (https://example.com/a?b=c). run `cargo test` or\n```rust\nfn main() {}\n```");
+        assert_eq!(vec![
+            Token::Word(String::from("see")),
+            Token::Url("https://example.com/a?b=c"),
+            Token::Word(String::from("run")),
+            Token::Code("`cargo test`"),
+            Token::Word(String::from("or")),
+            Token::Code("```rust\nfn main() {}\n```")
+        ], tokens);
+    }
+
+    #[test]
+    fn irc_formatting() {
+        let tokens = tokenize("\x02bold\x02 \x0304,12red\x03 \x04FF0000hex\x0F");
+        assert_eq!(vec![
+            Token::FormattingCode("\x02"),
+            Token::Word(String::from("bold")),
+            Token::FormattingCode("\x02"),
+            Token::FormattingCode("\x0304,12"),
+            Token::Word(String::from("red")),
+            Token::FormattingCode("\x03"),
+            Token::FormattingCode("\x04FF0000"),
+            Token::Word(String::from("hex")),
+            Token::FormattingCode("\x0F")
+        ], tokens);
+    }
+
+    #[test]
+    fn count_tokens() {
+        let counts = TokenCounts::of("the cat, 42 <@1> https://x.org `x` \x02 😀", |word| word != "42");
+        assert_eq!(TokenCounts {
+            dictionary_words: 2,
+            other_words: 1,
+            urls: 1,
+            mentions: 1,
+            emoji: 1,
+            formatting_codes: 1,
+            code_blocks: 1
+        }, counts);
+    }
+}
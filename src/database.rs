@@ -17,108 +17,558 @@
  * and navigate to version 3 of the GNU General Public License.
  */
 
-use sqlx::{PgPool, Row};
+mod migrations;
+mod postgres;
+mod sqlite;
+
+use std::fmt::Debug;
+use std::sync::Arc;
+use async_trait::async_trait;
+use sqlx::{ColumnIndex, Decode, PgPool, Row, Type};
 use eyre::Result;
+use crate::brain::Assessment;
+use crate::brain::tokenizer::TokenCounts;
+use crate::config::{DatabaseBackend, MessageRequirement};
+use self::postgres::PostgresStorage;
+use self::sqlite::SqliteStorage;
+
+/// Database access. Cloning is cheap as it simply increments a reference counter
+pub type Database = Arc<dyn Storage>;
 
-/// Database access. Cloning this struct is cheap as it simply increments a reference counter
-#[derive(Clone, Debug)]
-pub struct Database {
-    connection_pool: PgPool,
+/// Connects to the configured database. The Postgres connection is established lazily
+pub async fn connect(backend: &DatabaseBackend) -> Result<Database> {
+    Ok(match backend {
+        DatabaseBackend::Postgres { url } => {
+            Arc::new(PostgresStorage::from(PgPool::connect_lazy(url)?))
+        }
+        DatabaseBackend::Sqlite { path } => {
+            Arc::new(SqliteStorage::connect(path).await?)
+        }
+    })
 }
 
-impl From<PgPool> for Database {
-    fn from(connection_pool: PgPool) -> Self {
-        Self {
-            connection_pool
+/// The operations every storage backend provides
+#[async_trait]
+pub trait Storage: Send + Sync + Debug {
+    /// Applies pending schema migrations. Returns the resulting schema version
+    async fn migrate(&self) -> Result<i32>;
+
+    /// Records a message, creating its sender if necessary. Returns false if the message
+    /// was recorded previously, as may happen when a platform delivers it twice
+    async fn record_message(&self, message: &RecordedMessage<'_>) -> Result<bool>;
+
+    /// Gets the induction cycle which has not yet ended, if there is one
+    async fn open_induction_cycle(&self) -> Result<Option<InductionCycle>>;
+
+    async fn begin_induction_cycle(&self, started: u64) -> Result<InductionCycle>;
+
+    /// Finds the users who, between `started` (inclusive) and `ended` (exclusive), sent at least
+    /// `message_count` messages scoring at least `word_count` each
+    async fn users_meeting_requirement(&self,
+                                       started: u64, ended: u64,
+                                       requirement: &MessageRequirement) -> Result<Vec<i64>>;
+
+    /// Ends the given induction cycle, inducting the qualifying users and beginning the next
+    /// cycle at the moment this one ended. Returns the users who were newly inducted
+    async fn complete_induction_cycle(&self,
+                                      cycle: &InductionCycle, ended: u64,
+                                      qualifying_users: &[i64]) -> Result<Vec<i64>>;
+
+    /// Counts the messages counting toward induction which a user sent since the given time,
+    /// scoring at least `min_score`
+    async fn count_messages(&self, user_id: i64, since: u64, min_score: u32) -> Result<u64>;
+
+    /// Remembers a discord user's name, so that it may be displayed on other platforms
+    async fn set_discord_name(&self, discord_id: u64, discord_name: &str) -> Result<()>;
+
+    /// Ranks users by their messages sent between `since` (inclusive) and `until` (exclusive)
+    async fn leaderboard(&self,
+                         since: u64, until: u64,
+                         ranking: Ranking, limit: u32) -> Result<Vec<(User, u64)>>;
+
+    async fn users(&self, user_ids: &[i64]) -> Result<Vec<User>>;
+
+    async fn inducted_users(&self) -> Result<Vec<User>>;
+
+    async fn is_inducted(&self, user_identifier: UserIdentifier<'_>) -> Result<bool>;
+
+    async fn find_user(&self, user_identifier: UserIdentifier<'_>) -> Result<Option<i64>>;
+
+    /// Inducts a user outside of any induction cycle. Returns false if the user was already inducted
+    async fn induct(&self, user_id: i64) -> Result<bool>;
+
+    /// Revokes a user's induction. Returns false if the user was not inducted
+    async fn revoke_induction(&self, user_id: i64) -> Result<bool>;
+
+    /// Stores a code with which the given discord user may link an IRC nickname.
+    /// Any previous codes for the same user are replaced
+    async fn create_link_code(&self, discord_id: u64, code: &str, expires: u64) -> Result<()>;
+
+    /// Consumes a link code, linking the IRC nickname to the user who created the code.
+    /// If the nickname belongs to an IRC-only user, that user's messages and induction move
+    /// to the user who created the code. If it belongs to a user linked to another discord
+    /// account, nothing changes and the code stays valid, yielding [`Redemption::AlreadyLinked`].
+    /// Unknown or expired codes yield [`Redemption::InvalidCode`]
+    async fn redeem_link_code(&self, code: &str, irc_nickname: &str,
+                              now: u64) -> Result<Redemption>;
+
+    /// Stores a FAQ entry, answered when messages contain its keywords
+    async fn add_faq_entry(&self, keywords: &[String], answer: &str, created: u64) -> Result<FaqEntry>;
+
+    /// Returns false if there was no such entry
+    async fn remove_faq_entry(&self, id: i64) -> Result<bool>;
+
+    async fn faq_entries(&self) -> Result<Vec<FaqEntry>>;
+
+    /// Counts one more use of the FAQ entry
+    async fn record_faq_hit(&self, id: i64) -> Result<()>;
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Ranking {
+    /// Rank by the number of messages scoring at least the given score
+    QualifyingMessages { min_score: u32 },
+    /// Rank by the total score of all messages
+    TotalScore
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Redemption {
+    /// The code is unknown or expired
+    InvalidCode,
+    /// The nickname belongs to a user linked to another discord account, which is left alone
+    AlreadyLinked,
+    Linked(LinkedAccounts)
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LinkedAccounts {
+    pub user_id: i64,
+    /// Whether the linked user is inducted, whether before linking or by absorbing
+    /// an inducted IRC user
+    pub inducted: bool
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct User {
+    pub id: i64,
+    pub discord_id: Option<u64>,
+    /// The last known discord username
+    pub discord_name: Option<String>,
+    pub irc_nickname: Option<String>
+}
+
+impl User {
+    fn from_row<'r, R: Row>(row: &'r R) -> Result<Self>
+        where &'static str: ColumnIndex<R>,
+              i64: Decode<'r, R::Database> + Type<R::Database>,
+              String: Decode<'r, R::Database> + Type<R::Database> {
+        let discord_id: Option<i64> = row.try_get("discord_id")?;
+        Ok(Self {
+            id: row.try_get("id")?,
+            discord_id: discord_id.map(|discord_id| discord_id as u64),
+            discord_name: row.try_get("discord_name")?,
+            irc_nickname: row.try_get("irc_nickname")?
+        })
+    }
+
+    /// A name for this user which is readable on every platform
+    pub fn display_name(&self) -> String {
+        match (&self.irc_nickname, &self.discord_name) {
+            (Some(irc_nickname), _) => irc_nickname.clone(),
+            (None, Some(discord_name)) => discord_name.clone(),
+            (None, None) => format!("user #{}", self.id)
         }
     }
 }
 
-impl Database {
-    pub async fn create_schema(&self) -> Result<()> {
-        let mut connection = self.connection_pool.acquire().await?;
-
-        sqlx::query(r#"
-        CREATE TABLE IF NOT EXISTS "users" (
-          "id" BIGINT NOT NULL GENERATED BY DEFAULT AS IDENTITY,
-          "discord_id" BIGINT,
-          "irc_nickname" VARCHAR(64),
-          CONSTRAINT "users_id_uniqueness" UNIQUE ("id"),
-          CONSTRAINT "users_discord_id_uniqueness" UNIQUE ("discord_id"),
-          CONSTRAINT "users_irc_nickname_uniqueness" UNIQUE ("irc_nickname")
-        )
-        "#).execute(&mut connection).await?;
-        sqlx::query(r#"
-        CREATE TABLE IF NOT EXISTS "inducted" (
-          "user" BIGINT NOT NULL,
-          CONSTRAINT "inducted_user_uniqueness" UNIQUE ("id"),
-          CONSTRAINT "inducted_user_validity"
-            FOREIGN KEY ("user") REFERENCES "users" ("id") ON DELETE CASCADE ON UPDATE CASCADE
-        )
-        "#).execute(&mut connection).await?;
-        sqlx::query(r#"
-        CREATE TABLE IF NOT EXISTS "messages" (
-          "sent_by" BIGINT NOT NULL,
-          "word_count" INT NOT NULL,
-          "created" BIGINT NOT NULL,
-          CONSTRAINT "messages_sent_by_validity"
-            FOREIGN KEY ("sent_by") REFERENCES "users" ("id") ON DELETE CASCADE ON UPDATE CASCADE
-        )
-        "#).execute(&mut connection).await?;
-        sqlx::query(r#"
-        CREATE INDEX IF NOT EXISTS "messages_created_index" ON "messages" ("created")
-        "#).execute(&mut connection).await?;
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct InductionCycle {
+    pub id: i64,
+    pub started: u64
+}
 
-        Ok(())
+impl InductionCycle {
+    fn from_row<'r, R: Row>(row: &'r R) -> Result<Self>
+        where &'static str: ColumnIndex<R>,
+              i64: Decode<'r, R::Database> + Type<R::Database> {
+        let started: i64 = row.try_get("started")?;
+        Ok(Self {
+            id: row.try_get("id")?,
+            started: started as u64
+        })
     }
+}
 
-    pub async fn record_message(&self,
-                                user_identifier: UserIdentifier<'_>,
-                                word_count: u32) -> Result<()> {
-        let mut transaction = self.connection_pool.begin().await?;
-
-        let user_id_row = match user_identifier {
-            UserIdentifier::DiscordId(discord_id) => {
-                let discord_id = discord_id as i64;
-                sqlx::query(r#"
-                INSERT INTO "users" ("discord_id") VALUES (?)
-                  ON CONFLICT ("discord_id") DO NOTHING
-                "#).bind(discord_id).execute(&mut transaction).await?;
-                sqlx::query(r#"
-                SELECT "id" FROM "users" WHERE "discord_id" = ?
-                "#).bind(discord_id).fetch_one(&mut transaction).await?
-            }
-            UserIdentifier::IrcNickname(irc_nickname) => {
-                sqlx::query(r#"
-                INSERT INTO "users" ("irc_nickname") VALUES (?)
-                  ON CONFLICT ("irc_nickname") DO NOTHING
-                "#).bind(irc_nickname).execute(&mut transaction).await?;
-                sqlx::query(r#"
-                SELECT "id" FROM "users" WHERE "irc_nickname" = ?
-                "#).bind(irc_nickname).fetch_one(&mut transaction).await?
-            }
-        };
-        let user_id: i64 = user_id_row.try_get("id")?;
-
-        use std::time::{SystemTime, UNIX_EPOCH};
-        let creation_time = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .expect("Failed to obtain duration since unix epoch")
-            .as_secs();
-
-        sqlx::query(r#"
-        INSERT INTO "messages" ("sent_by", "word_count", "created") VALUES (?, ?, ?)
-        "#)
-            .bind(user_id)
-            .bind(word_count as i32)
-            .bind(creation_time as i64)
-            .execute(&mut transaction)
-            .await?;
-        Ok(())
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FaqEntry {
+    pub id: i64,
+    /// Lowercase words, which are stored separated by spaces
+    pub keywords: Vec<String>,
+    pub answer: String,
+    /// How many times the entry answered a message
+    pub hits: u64
+}
+
+impl FaqEntry {
+    fn from_row<'r, R: Row>(row: &'r R) -> Result<Self>
+        where &'static str: ColumnIndex<R>,
+              i64: Decode<'r, R::Database> + Type<R::Database>,
+              String: Decode<'r, R::Database> + Type<R::Database> {
+        let keywords: String = row.try_get("keywords")?;
+        let hits: i64 = row.try_get("hits")?;
+        Ok(Self {
+            id: row.try_get("id")?,
+            keywords: keywords.split_whitespace().map(String::from).collect(),
+            answer: row.try_get("answer")?,
+            hits: hits as u64
+        })
     }
 }
 
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum UserIdentifier<'n> {
     DiscordId(u64),
     IrcNickname(&'n str)
 }
+
+impl UserIdentifier<'_> {
+    pub fn platform(&self) -> Platform {
+        match self {
+            Self::DiscordId(_) => Platform::Discord,
+            Self::IrcNickname(_) => Platform::Irc
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Platform {
+    Discord,
+    Irc
+}
+
+impl Platform {
+    /// The name stored in the database
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Discord => "discord",
+            Self::Irc => "irc"
+        }
+    }
+}
+
+/// A message to be recorded, along with where it was sent
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RecordedMessage<'m> {
+    pub sender: UserIdentifier<'m>,
+    /// The IRC channel or discord channel ID, or `None` for private messages
+    pub channel: Option<&'m str>,
+    pub guild_id: Option<u64>,
+    /// The platform's own ID for the message, if it provides one
+    pub message_id: Option<&'m str>,
+    /// The number of dictionary words and other kinds of tokens
+    pub tokens: TokenCounts,
+    pub char_count: u32,
+    /// Whether the message counts toward induction. Messages which do not count are still
+    /// recorded, but are excluded from induction, stats and leaderboards
+    pub counted: bool,
+    /// The quality of the message, measured in words. Induction relies on this score
+    pub score: u32
+}
+
+impl<'m> RecordedMessage<'m> {
+    /// Describes the message content and its assessment. The location and message ID are left unset,
+    /// and the message counts toward induction
+    pub fn new(sender: UserIdentifier<'m>, content: &str, assessment: Assessment) -> Self {
+        Self {
+            sender,
+            channel: None,
+            guild_id: None,
+            message_id: None,
+            tokens: assessment.tokens,
+            char_count: content.chars().count() as u32,
+            counted: true,
+            score: assessment.score
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod test_support {
+    use std::str::FromStr;
+    use std::sync::Arc;
+    use eyre::Result;
+    use async_std::task;
+    use rand::Rng;
+    use sqlx::PgPool;
+    use sqlx::postgres::PgConnectOptions;
+    use tempfile::TempDir;
+    use super::Database;
+    use super::postgres::PostgresStorage;
+    use super::sqlite::SqliteStorage;
+
+    /// Opens a SQLite database inside the given directory, which is deleted with the directory
+    pub async fn sqlite_in(tempdir: &TempDir) -> Result<Database> {
+        let path = tempdir.path().join("faithful-servant.db");
+        Ok(Arc::new(SqliteStorage::connect(&path).await?))
+    }
+
+    /// A database created for a single test, on the Postgres server given by TEST_POSTGRES_URL.
+    /// It is dropped along with this value, even if the test panics
+    pub struct ThrowawayDatabase {
+        pub connection_pool: PgPool,
+        server_pool: PgPool,
+        name: String
+    }
+
+    impl ThrowawayDatabase {
+        /// Creates the database, failing if TEST_POSTGRES_URL is not set
+        pub async fn create() -> Result<Self> {
+            let url = std::env::var("TEST_POSTGRES_URL")
+                .map_err(|_| eyre::eyre!("Tests needing Postgres require TEST_POSTGRES_URL to be set"))?;
+            let server_pool = PgPool::connect(&url).await?;
+            let name = format!("faithful_servant_test_{}", rand::thread_rng().gen::<u32>());
+            sqlx::query(&format!(r#"CREATE DATABASE "{}""#, name)).execute(&server_pool).await?;
+
+            let connection_pool = PgPool::connect_with(
+                PgConnectOptions::from_str(&url)?.database(&name)
+            ).await?;
+            Ok(Self {
+                connection_pool,
+                server_pool,
+                name
+            })
+        }
+
+        pub fn database(&self) -> Database {
+            Arc::new(PostgresStorage::from(self.connection_pool.clone()))
+        }
+
+    }
+
+    impl Drop for ThrowawayDatabase {
+        fn drop(&mut self) {
+            let result = task::block_on(async {
+                self.connection_pool.close().await;
+                sqlx::query(&format!(r#"DROP DATABASE "{}""#, self.name)).execute(&self.server_pool).await
+            });
+            // Panicking again during a panic would abort the test run
+            if let Err(e) = result {
+                if !std::thread::panicking() {
+                    panic!("Failed to drop the throwaway database {}: {}", self.name, e);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(sender: UserIdentifier<'_>, word_count: u32) -> RecordedMessage<'_> {
+        RecordedMessage {
+            sender,
+            channel: None,
+            guild_id: None,
+            message_id: None,
+            tokens: TokenCounts {
+                dictionary_words: word_count,
+                ..TokenCounts::default()
+            },
+            char_count: word_count * 5,
+            counted: true,
+            score: word_count
+        }
+    }
+
+    /// Tests shared by every storage backend
+    async fn check_record_and_find_users(database: Database) -> Result<()> {
+        database.record_message(&message(UserIdentifier::DiscordId(1234), 5)).await?;
+        database.record_message(&message(UserIdentifier::IrcNickname("A248"), 3)).await?;
+        database.record_message(&message(UserIdentifier::IrcNickname("A248"), 12)).await?;
+
+        let discord_user = database.find_user(UserIdentifier::DiscordId(1234)).await?.unwrap();
+        let irc_user = database.find_user(UserIdentifier::IrcNickname("A248")).await?.unwrap();
+        assert_ne!(discord_user, irc_user);
+        assert_eq!(None, database.find_user(UserIdentifier::IrcNickname("nobody")).await?);
+
+        assert_eq!(1, database.count_messages(discord_user, 0, 0).await?);
+        assert_eq!(2, database.count_messages(irc_user, 0, 0).await?);
+        let uncounted = RecordedMessage {
+            counted: false,
+            ..message(UserIdentifier::DiscordId(1234), 20)
+        };
+        assert!(database.record_message(&uncounted).await?);
+        assert_eq!(1, database.count_messages(discord_user, 0, 0).await?);
+        assert_eq!(1, database.count_messages(irc_user, 0, 10).await?);
+
+        database.set_discord_name(1234, "Anand").await?;
+        let mut users = database.users(&[discord_user, irc_user]).await?;
+        users.sort_by_key(|user| user.id);
+        let names = users.iter().map(User::display_name).collect::<Vec<_>>();
+        let expected = if discord_user < irc_user { ["Anand", "A248"] } else { ["A248", "Anand"] };
+        assert_eq!(expected.to_vec(), names);
+        assert!(database.users(&[]).await?.is_empty());
+        Ok(())
+    }
+
+    async fn check_duplicate_messages(database: Database) -> Result<()> {
+        let recorded = RecordedMessage {
+            message_id: Some("5678"),
+            channel: Some("1111"),
+            guild_id: Some(2222),
+            ..message(UserIdentifier::DiscordId(1234), 4)
+        };
+        assert!(database.record_message(&recorded).await?);
+        assert!(!database.record_message(&recorded).await?);
+        // Message IDs are only unique within a platform
+        let on_irc = RecordedMessage {
+            message_id: Some("5678"),
+            channel: Some("#general"),
+            ..message(UserIdentifier::IrcNickname("A248"), 4)
+        };
+        assert!(database.record_message(&on_irc).await?);
+        // Messages without an ID are never considered duplicates
+        assert!(database.record_message(&message(UserIdentifier::IrcNickname("A248"), 4)).await?);
+        assert!(database.record_message(&message(UserIdentifier::IrcNickname("A248"), 4)).await?);
+
+        let discord_user = database.find_user(UserIdentifier::DiscordId(1234)).await?.unwrap();
+        assert_eq!(1, database.count_messages(discord_user, 0, 0).await?);
+        Ok(())
+    }
+
+    async fn check_induction_cycle(database: Database) -> Result<()> {
+        assert_eq!(None, database.open_induction_cycle().await?);
+        let cycle = database.begin_induction_cycle(0).await?;
+        assert_eq!(Some(cycle.clone()), database.open_induction_cycle().await?);
+
+        database.record_message(&message(UserIdentifier::IrcNickname("A248"), 10)).await?;
+        database.record_message(&message(UserIdentifier::IrcNickname("A248"), 10)).await?;
+        database.record_message(&message(UserIdentifier::IrcNickname("lurker"), 10)).await?;
+        let requirement = MessageRequirement { message_count: 2, word_count: 8 };
+        let ended = crate::current_unix_time() + 1;
+        let qualifying = database.users_meeting_requirement(0, ended, &requirement).await?;
+        let user_id = database.find_user(UserIdentifier::IrcNickname("A248")).await?.unwrap();
+        assert_eq!(vec![user_id], qualifying);
+
+        assert_eq!(qualifying, database.complete_induction_cycle(&cycle, ended, &qualifying).await?);
+        assert!(database.is_inducted(UserIdentifier::IrcNickname("A248")).await?);
+        assert!(!database.is_inducted(UserIdentifier::IrcNickname("lurker")).await?);
+
+        let next_cycle = database.open_induction_cycle().await?.unwrap();
+        assert_ne!(cycle.id, next_cycle.id);
+        assert_eq!(ended, next_cycle.started);
+        // Users already inducted are not inducted again
+        assert!(database.complete_induction_cycle(&next_cycle, ended + 1, &qualifying).await?.is_empty());
+
+        assert!(database.revoke_induction(user_id).await?);
+        assert!(!database.revoke_induction(user_id).await?);
+        assert!(database.induct(user_id).await?);
+        assert_eq!(vec![user_id], database.inducted_users().await?.iter().map(|user| user.id).collect::<Vec<_>>());
+        Ok(())
+    }
+
+    async fn check_link_accounts(database: Database) -> Result<()> {
+        database.record_message(&message(UserIdentifier::IrcNickname("A248"), 4)).await?;
+        let irc_user = database.find_user(UserIdentifier::IrcNickname("A248")).await?.unwrap();
+        assert!(database.induct(irc_user).await?);
+
+        database.create_link_code(1234, "EXPIRED", 100).await?;
+        assert_eq!(Redemption::InvalidCode, database.redeem_link_code("EXPIRED", "A248", 100).await?);
+        database.create_link_code(1234, "ABCDEFGH", 200).await?;
+        assert_eq!(Redemption::InvalidCode, database.redeem_link_code("UNKNOWN", "A248", 100).await?);
+
+        let linked = match database.redeem_link_code("ABCDEFGH", "A248", 100).await? {
+            Redemption::Linked(linked) => linked,
+            other => panic!("Not linked: {:?}", other)
+        };
+        assert!(linked.inducted);
+        assert_eq!(Some(linked.user_id), database.find_user(UserIdentifier::DiscordId(1234)).await?);
+        assert_eq!(Some(linked.user_id), database.find_user(UserIdentifier::IrcNickname("A248")).await?);
+        assert_eq!(1, database.count_messages(linked.user_id, 0, 0).await?);
+        // Codes may only be used once
+        assert_eq!(Redemption::InvalidCode, database.redeem_link_code("ABCDEFGH", "A248", 100).await?);
+
+        // A nickname linked to one discord account cannot be taken by another
+        database.create_link_code(5678, "IJKLMNPQ", 200).await?;
+        assert_eq!(Redemption::AlreadyLinked, database.redeem_link_code("IJKLMNPQ", "A248", 100).await?);
+        assert_eq!(Some(linked.user_id), database.find_user(UserIdentifier::IrcNickname("A248")).await?);
+        Ok(())
+    }
+
+    async fn check_leaderboard(database: Database) -> Result<()> {
+        database.record_message(&message(UserIdentifier::IrcNickname("wordy"), 50)).await?;
+        database.record_message(&message(UserIdentifier::IrcNickname("chatty"), 3)).await?;
+        database.record_message(&message(UserIdentifier::IrcNickname("chatty"), 6)).await?;
+        database.record_message(&message(UserIdentifier::IrcNickname("chatty"), 6)).await?;
+
+        let names = |ranked: Vec<(User, u64)>| {
+            ranked.into_iter().map(|(user, score)| (user.display_name(), score)).collect::<Vec<_>>()
+        };
+        let by_messages = database.leaderboard(
+            0, u64::MAX, Ranking::QualifyingMessages { min_score: 5 }, 10
+        ).await?;
+        assert_eq!(vec![("chatty".to_string(), 2), ("wordy".to_string(), 1)], names(by_messages));
+        let by_score = database.leaderboard(0, u64::MAX, Ranking::TotalScore, 1).await?;
+        assert_eq!(vec![("wordy".to_string(), 50)], names(by_score));
+        assert!(database.leaderboard(0, 1, Ranking::TotalScore, 10).await?.is_empty());
+        Ok(())
+    }
+
+    async fn check_faq_entries(database: Database) -> Result<()> {
+        let keywords = vec![String::from("join"), String::from("irc")];
+        let entry = database.add_faq_entry(&keywords, "Connect to irc.example.com", 100).await?;
+        let other = database.add_faq_entry(&[String::from("rules")], "Be nice", 100).await?;
+        assert_eq!(keywords, entry.keywords);
+        assert_eq!(0, entry.hits);
+
+        database.record_faq_hit(entry.id).await?;
+        database.record_faq_hit(entry.id).await?;
+        let entries = database.faq_entries().await?;
+        assert_eq!(vec![FaqEntry { hits: 2, ..entry.clone() }, other.clone()], entries);
+
+        assert!(database.remove_faq_entry(other.id).await?);
+        assert!(!database.remove_faq_entry(other.id).await?);
+        assert_eq!(1, database.faq_entries().await?.len());
+        Ok(())
+    }
+
+    /// Generates a test module for each check, running it against every storage backend
+    macro_rules! storage_tests {
+        ($($name:ident => $check:ident),* $(,)?) => {
+            $(
+                mod $name {
+                    use eyre::Result;
+                    use crate::database::test_support::{self, ThrowawayDatabase};
+
+                    #[async_std::test]
+                    async fn sqlite() -> Result<()> {
+                        let tempdir = tempfile::tempdir()?;
+                        let database = test_support::sqlite_in(&tempdir).await?;
+                        database.migrate().await?;
+                        super::$check(database).await
+                    }
+
+                    #[async_std::test]
+                    #[ignore = "requires a Postgres server at TEST_POSTGRES_URL"]
+                    async fn postgres() -> Result<()> {
+                        let throwaway = ThrowawayDatabase::create().await?;
+                        let database = throwaway.database();
+                        database.migrate().await?;
+                        super::$check(database).await
+                    }
+                }
+            )*
+        };
+    }
+
+    storage_tests! {
+        record_and_find_users => check_record_and_find_users,
+        duplicate_messages => check_duplicate_messages,
+        induction_cycle => check_induction_cycle,
+        link_accounts => check_link_accounts,
+        leaderboard => check_leaderboard,
+        faq_entries => check_faq_entries
+    }
+}
@@ -0,0 +1,343 @@
+/*
+ * faithful-servant-bot
+ * Copyright © 2022 Anand Beh
+ *
+ * faithful-servant-bot is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * faithful-servant-bot is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with faithful-servant-bot. If not, see <https://www.gnu.org/licenses/>
+ * and navigate to version 3 of the GNU General Public License.
+ */
+
+/// A numbered change to the schema. Once released, a migration must never be altered;
+/// further changes belong in a new migration
+#[derive(Debug)]
+pub struct Migration {
+    pub version: i32,
+    pub description: &'static str,
+    pub statements: &'static [&'static str]
+}
+
+pub const POSTGRES: &[Migration] = &[
+    Migration {
+        version: 1,
+        description: "Create users and messages",
+        // Deployments predating migrations may already have these tables
+        statements: &[r#"
+        CREATE TABLE IF NOT EXISTS "users" (
+          "id" BIGINT NOT NULL GENERATED BY DEFAULT AS IDENTITY,
+          "discord_id" BIGINT,
+          "irc_nickname" VARCHAR(64),
+          CONSTRAINT "users_id_uniqueness" UNIQUE ("id"),
+          CONSTRAINT "users_discord_id_uniqueness" UNIQUE ("discord_id"),
+          CONSTRAINT "users_irc_nickname_uniqueness" UNIQUE ("irc_nickname")
+        )
+        "#, r#"
+        CREATE TABLE IF NOT EXISTS "messages" (
+          "sent_by" BIGINT NOT NULL,
+          "word_count" INT NOT NULL,
+          "created" BIGINT NOT NULL,
+          CONSTRAINT "messages_sent_by_validity"
+            FOREIGN KEY ("sent_by") REFERENCES "users" ("id") ON DELETE CASCADE ON UPDATE CASCADE
+        )
+        "#, r#"
+        CREATE INDEX IF NOT EXISTS "messages_created_index" ON "messages" ("created")
+        "#]
+    },
+    Migration {
+        version: 2,
+        description: "Record induction cycles and inducted users",
+        statements: &[r#"
+        CREATE TABLE "induction_cycles" (
+          "id" BIGINT NOT NULL GENERATED BY DEFAULT AS IDENTITY,
+          "started" BIGINT NOT NULL,
+          "ended" BIGINT,
+          "qualified_count" INT,
+          "inducted_count" INT,
+          CONSTRAINT "induction_cycles_id_uniqueness" UNIQUE ("id")
+        )
+        "#, r#"
+        CREATE TABLE "inducted" (
+          "user" BIGINT NOT NULL,
+          "cycle" BIGINT,
+          CONSTRAINT "inducted_user_uniqueness" UNIQUE ("user"),
+          CONSTRAINT "inducted_user_validity"
+            FOREIGN KEY ("user") REFERENCES "users" ("id") ON DELETE CASCADE ON UPDATE CASCADE,
+          CONSTRAINT "inducted_cycle_validity"
+            FOREIGN KEY ("cycle") REFERENCES "induction_cycles" ("id") ON DELETE SET NULL ON UPDATE CASCADE
+        )
+        "#]
+    },
+    Migration {
+        version: 3,
+        description: "Add link codes for linking accounts",
+        statements: &[r#"
+        CREATE TABLE "link_codes" (
+          "code" VARCHAR(16) NOT NULL,
+          "user" BIGINT NOT NULL,
+          "expires" BIGINT NOT NULL,
+          CONSTRAINT "link_codes_code_uniqueness" UNIQUE ("code"),
+          CONSTRAINT "link_codes_user_validity"
+            FOREIGN KEY ("user") REFERENCES "users" ("id") ON DELETE CASCADE ON UPDATE CASCADE
+        )
+        "#]
+    },
+    Migration {
+        version: 4,
+        description: "Remember discord names",
+        statements: &[r#"
+        ALTER TABLE "users" ADD COLUMN "discord_name" VARCHAR(64)
+        "#]
+    },
+    Migration {
+        version: 5,
+        description: "Record where messages were sent",
+        statements: &[r#"
+        ALTER TABLE "messages"
+          ADD COLUMN "platform" VARCHAR(16),
+          ADD COLUMN "channel" VARCHAR(128),
+          ADD COLUMN "guild_id" BIGINT,
+          ADD COLUMN "message_id" VARCHAR(128),
+          ADD COLUMN "char_count" INT
+        "#, r#"
+        CREATE UNIQUE INDEX "messages_message_id_uniqueness" ON "messages" ("platform", "message_id")
+        "#]
+    },
+    Migration {
+        version: 6,
+        description: "Distinguish messages which count toward induction",
+        statements: &[r#"
+        ALTER TABLE "messages" ADD COLUMN "counted" BOOLEAN NOT NULL DEFAULT TRUE
+        "#]
+    },
+    Migration {
+        version: 7,
+        description: "Score messages by their quality",
+        statements: &[r#"
+        ALTER TABLE "messages" ADD COLUMN "score" INT NOT NULL DEFAULT 0
+        "#, r#"
+        UPDATE "messages" SET "score" = "word_count"
+        "#]
+    },
+    Migration {
+        version: 8,
+        description: "Count the kinds of tokens in messages",
+        statements: &[r#"
+        ALTER TABLE "messages"
+          ADD COLUMN "other_word_count" INT,
+          ADD COLUMN "url_count" INT,
+          ADD COLUMN "mention_count" INT,
+          ADD COLUMN "emoji_count" INT,
+          ADD COLUMN "formatting_code_count" INT,
+          ADD COLUMN "code_block_count" INT
+        "#]
+    },
+    Migration {
+        version: 9,
+        description: "Store frequently asked questions",
+        statements: &[r#"
+        CREATE TABLE "faq_entries" (
+          "id" BIGINT NOT NULL GENERATED BY DEFAULT AS IDENTITY,
+          "keywords" TEXT NOT NULL,
+          "answer" TEXT NOT NULL,
+          "hits" BIGINT NOT NULL DEFAULT 0,
+          "created" BIGINT NOT NULL,
+          CONSTRAINT "faq_entries_id_uniqueness" UNIQUE ("id")
+        )
+        "#]
+    }
+];
+
+pub const SQLITE: &[Migration] = &[
+    Migration {
+        version: 1,
+        description: "Create users and messages",
+        statements: &[r#"
+        CREATE TABLE "users" (
+          "id" INTEGER NOT NULL PRIMARY KEY,
+          "discord_id" INTEGER UNIQUE,
+          "irc_nickname" TEXT UNIQUE
+        )
+        "#, r#"
+        CREATE TABLE "messages" (
+          "sent_by" INTEGER NOT NULL
+            REFERENCES "users" ("id") ON DELETE CASCADE ON UPDATE CASCADE,
+          "word_count" INTEGER NOT NULL,
+          "created" INTEGER NOT NULL
+        )
+        "#, r#"
+        CREATE INDEX "messages_created_index" ON "messages" ("created")
+        "#]
+    },
+    Migration {
+        version: 2,
+        description: "Record induction cycles and inducted users",
+        statements: &[r#"
+        CREATE TABLE "induction_cycles" (
+          "id" INTEGER NOT NULL PRIMARY KEY,
+          "started" INTEGER NOT NULL,
+          "ended" INTEGER,
+          "qualified_count" INTEGER,
+          "inducted_count" INTEGER
+        )
+        "#, r#"
+        CREATE TABLE "inducted" (
+          "user" INTEGER NOT NULL UNIQUE
+            REFERENCES "users" ("id") ON DELETE CASCADE ON UPDATE CASCADE,
+          "cycle" INTEGER
+            REFERENCES "induction_cycles" ("id") ON DELETE SET NULL ON UPDATE CASCADE
+        )
+        "#]
+    },
+    Migration {
+        version: 3,
+        description: "Add link codes for linking accounts",
+        statements: &[r#"
+        CREATE TABLE "link_codes" (
+          "code" TEXT NOT NULL UNIQUE,
+          "user" INTEGER NOT NULL
+            REFERENCES "users" ("id") ON DELETE CASCADE ON UPDATE CASCADE,
+          "expires" INTEGER NOT NULL
+        )
+        "#]
+    },
+    Migration {
+        version: 4,
+        description: "Remember discord names",
+        statements: &[r#"
+        ALTER TABLE "users" ADD COLUMN "discord_name" TEXT
+        "#]
+    },
+    Migration {
+        version: 5,
+        description: "Record where messages were sent",
+        statements: &[r#"
+        ALTER TABLE "messages" ADD COLUMN "platform" TEXT
+        "#, r#"
+        ALTER TABLE "messages" ADD COLUMN "channel" TEXT
+        "#, r#"
+        ALTER TABLE "messages" ADD COLUMN "guild_id" INTEGER
+        "#, r#"
+        ALTER TABLE "messages" ADD COLUMN "message_id" TEXT
+        "#, r#"
+        ALTER TABLE "messages" ADD COLUMN "char_count" INTEGER
+        "#, r#"
+        CREATE UNIQUE INDEX "messages_message_id_uniqueness" ON "messages" ("platform", "message_id")
+        "#]
+    },
+    Migration {
+        version: 6,
+        description: "Distinguish messages which count toward induction",
+        statements: &[r#"
+        ALTER TABLE "messages" ADD COLUMN "counted" INTEGER NOT NULL DEFAULT 1
+        "#]
+    },
+    Migration {
+        version: 7,
+        description: "Score messages by their quality",
+        statements: &[r#"
+        ALTER TABLE "messages" ADD COLUMN "score" INTEGER NOT NULL DEFAULT 0
+        "#, r#"
+        UPDATE "messages" SET "score" = "word_count"
+        "#]
+    },
+    Migration {
+        version: 8,
+        description: "Count the kinds of tokens in messages",
+        statements: &[r#"
+        ALTER TABLE "messages" ADD COLUMN "other_word_count" INTEGER
+        "#, r#"
+        ALTER TABLE "messages" ADD COLUMN "url_count" INTEGER
+        "#, r#"
+        ALTER TABLE "messages" ADD COLUMN "mention_count" INTEGER
+        "#, r#"
+        ALTER TABLE "messages" ADD COLUMN "emoji_count" INTEGER
+        "#, r#"
+        ALTER TABLE "messages" ADD COLUMN "formatting_code_count" INTEGER
+        "#, r#"
+        ALTER TABLE "messages" ADD COLUMN "code_block_count" INTEGER
+        "#]
+    },
+    Migration {
+        version: 9,
+        description: "Store frequently asked questions",
+        statements: &[r#"
+        CREATE TABLE "faq_entries" (
+          "id" INTEGER NOT NULL PRIMARY KEY,
+          "keywords" TEXT NOT NULL,
+          "answer" TEXT NOT NULL,
+          "hits" INTEGER NOT NULL DEFAULT 0,
+          "created" INTEGER NOT NULL
+        )
+        "#]
+    }
+];
+
+pub fn warn_if_newer(current_version: i32, migrations: &[Migration]) {
+    if let Some(latest) = migrations.last() {
+        if current_version > latest.version {
+            log::warn!("The schema version {} is newer than this release supports ({})",
+                       current_version, latest.version);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use eyre::Result;
+    use super::*;
+    use crate::database::test_support::{self, ThrowawayDatabase};
+
+    #[test]
+    fn versions_are_sequential() {
+        for migrations in [POSTGRES, SQLITE] {
+            for (index, migration) in migrations.iter().enumerate() {
+                assert_eq!(index as i32 + 1, migration.version);
+            }
+        }
+    }
+
+    #[test]
+    fn backends_have_the_same_migrations() {
+        let descriptions = |migrations: &[Migration]| {
+            migrations.iter().map(|migration| migration.description).collect::<Vec<_>>()
+        };
+        assert_eq!(descriptions(POSTGRES), descriptions(SQLITE));
+    }
+
+    #[async_std::test]
+    #[ignore = "requires a Postgres server at TEST_POSTGRES_URL"]
+    async fn apply_every_postgres_migration() -> Result<()> {
+        let throwaway = ThrowawayDatabase::create().await?;
+        let version = throwaway.database().migrate().await?;
+        assert_eq!(POSTGRES.last().unwrap().version, version);
+        Ok(())
+    }
+
+    #[async_std::test]
+    #[ignore = "requires a Postgres server at TEST_POSTGRES_URL"]
+    async fn migrating_postgres_twice_changes_nothing() -> Result<()> {
+        let throwaway = ThrowawayDatabase::create().await?;
+        let first = throwaway.database().migrate().await?;
+        let second = throwaway.database().migrate().await?;
+        assert_eq!(first, second);
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn apply_every_sqlite_migration() -> Result<()> {
+        let tempdir = tempfile::tempdir()?;
+        let database = test_support::sqlite_in(&tempdir).await?;
+        assert_eq!(SQLITE.last().unwrap().version, database.migrate().await?);
+        assert_eq!(SQLITE.last().unwrap().version, database.migrate().await?);
+        Ok(())
+    }
+}
@@ -0,0 +1,560 @@
+/*
+ * faithful-servant-bot
+ * Copyright © 2022 Anand Beh
+ *
+ * faithful-servant-bot is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * faithful-servant-bot is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with faithful-servant-bot. If not, see <https://www.gnu.org/licenses/>
+ * and navigate to version 3 of the GNU General Public License.
+ */
+
+use async_trait::async_trait;
+use sqlx::{PgPool, Row};
+use eyre::Result;
+use crate::config::MessageRequirement;
+use super::{
+    migrations, FaqEntry, InductionCycle, LinkedAccounts, Ranking, RecordedMessage, Redemption, Storage, User,
+    UserIdentifier
+};
+
+/// Storage in a Postgres database
+#[derive(Debug)]
+pub struct PostgresStorage {
+    connection_pool: PgPool
+}
+
+impl From<PgPool> for PostgresStorage {
+    fn from(connection_pool: PgPool) -> Self {
+        Self {
+            connection_pool
+        }
+    }
+}
+
+#[async_trait]
+impl Storage for PostgresStorage {
+    async fn migrate(&self) -> Result<i32> {
+        let mut connection = self.connection_pool.acquire().await?;
+        sqlx::query(r#"
+        CREATE TABLE IF NOT EXISTS "schema_version" (
+          "version" INT NOT NULL,
+          "description" VARCHAR(256) NOT NULL,
+          "applied" BIGINT NOT NULL,
+          CONSTRAINT "schema_version_version_uniqueness" UNIQUE ("version")
+        )
+        "#).execute(&mut connection).await?;
+        drop(connection);
+
+        let mut current_version = 0;
+        for migration in migrations::POSTGRES {
+            let mut transaction = self.connection_pool.begin().await?;
+            // Prevent other instances from migrating concurrently
+            sqlx::query(r#"
+            LOCK TABLE "schema_version" IN EXCLUSIVE MODE
+            "#).execute(&mut transaction).await?;
+
+            current_version = sqlx::query(r#"
+            SELECT COALESCE(MAX("version"), 0) AS "version" FROM "schema_version"
+            "#).fetch_one(&mut transaction).await?.try_get("version")?;
+            if migration.version <= current_version {
+                continue;
+            }
+            log::info!("Migrating schema to version {}: {}", migration.version, migration.description);
+            for statement in migration.statements {
+                sqlx::query(statement).execute(&mut transaction).await?;
+            }
+            sqlx::query(r#"
+            INSERT INTO "schema_version" ("version", "description", "applied") VALUES ($1, $2, $3)
+            "#)
+                .bind(migration.version)
+                .bind(migration.description)
+                .bind(crate::current_unix_time() as i64)
+                .execute(&mut transaction)
+                .await?;
+            transaction.commit().await?;
+            current_version = migration.version;
+        }
+        migrations::warn_if_newer(current_version, migrations::POSTGRES);
+        Ok(current_version)
+    }
+
+    async fn record_message(&self, message: &RecordedMessage<'_>) -> Result<bool> {
+        let mut transaction = self.connection_pool.begin().await?;
+
+        let user_id_row = match message.sender {
+            UserIdentifier::DiscordId(discord_id) => {
+                let discord_id = discord_id as i64;
+                sqlx::query(r#"
+                INSERT INTO "users" ("discord_id") VALUES ($1)
+                  ON CONFLICT ("discord_id") DO NOTHING
+                "#).bind(discord_id).execute(&mut transaction).await?;
+                sqlx::query(r#"
+                SELECT "id" FROM "users" WHERE "discord_id" = $1
+                "#).bind(discord_id).fetch_one(&mut transaction).await?
+            }
+            UserIdentifier::IrcNickname(irc_nickname) => {
+                sqlx::query(r#"
+                INSERT INTO "users" ("irc_nickname") VALUES ($1)
+                  ON CONFLICT ("irc_nickname") DO NOTHING
+                "#).bind(irc_nickname).execute(&mut transaction).await?;
+                sqlx::query(r#"
+                SELECT "id" FROM "users" WHERE "irc_nickname" = $1
+                "#).bind(irc_nickname).fetch_one(&mut transaction).await?
+            }
+        };
+        let user_id: i64 = user_id_row.try_get("id")?;
+
+        let creation_time = crate::current_unix_time();
+
+        let result = sqlx::query(r#"
+        INSERT INTO "messages" ("sent_by", "word_count", "created",
+                                "platform", "channel", "guild_id", "message_id", "char_count", "counted", "score",
+                                "other_word_count", "url_count", "mention_count", "emoji_count",
+                                "formatting_code_count", "code_block_count")
+          VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16)
+          ON CONFLICT ("platform", "message_id") DO NOTHING
+        "#)
+            .bind(user_id)
+            .bind(message.tokens.dictionary_words as i32)
+            .bind(creation_time as i64)
+            .bind(message.sender.platform().as_str())
+            .bind(message.channel)
+            .bind(message.guild_id.map(|guild_id| guild_id as i64))
+            .bind(message.message_id)
+            .bind(message.char_count as i32)
+            .bind(message.counted)
+            .bind(message.score as i32)
+            .bind(message.tokens.other_words as i32)
+            .bind(message.tokens.urls as i32)
+            .bind(message.tokens.mentions as i32)
+            .bind(message.tokens.emoji as i32)
+            .bind(message.tokens.formatting_codes as i32)
+            .bind(message.tokens.code_blocks as i32)
+            .execute(&mut transaction)
+            .await?;
+
+        transaction.commit().await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn open_induction_cycle(&self) -> Result<Option<InductionCycle>> {
+        let mut connection = self.connection_pool.acquire().await?;
+
+        let cycle_row = sqlx::query(r#"
+        SELECT "id", "started" FROM "induction_cycles" WHERE "ended" IS NULL
+          ORDER BY "started" DESC LIMIT 1
+        "#).fetch_optional(&mut connection).await?;
+
+        cycle_row.map(|cycle_row| InductionCycle::from_row(&cycle_row)).transpose()
+    }
+
+    async fn begin_induction_cycle(&self, started: u64) -> Result<InductionCycle> {
+        let mut connection = self.connection_pool.acquire().await?;
+
+        let cycle_row = sqlx::query(r#"
+        INSERT INTO "induction_cycles" ("started") VALUES ($1) RETURNING "id", "started"
+        "#).bind(started as i64).fetch_one(&mut connection).await?;
+
+        InductionCycle::from_row(&cycle_row)
+    }
+
+    async fn users_meeting_requirement(&self,
+                                       started: u64, ended: u64,
+                                       requirement: &MessageRequirement) -> Result<Vec<i64>> {
+        let mut connection = self.connection_pool.acquire().await?;
+
+        let user_rows = sqlx::query(r#"
+        SELECT "sent_by" FROM "messages"
+          WHERE "created" >= $1 AND "created" < $2 AND "score" >= $3
+            AND "counted"
+          GROUP BY "sent_by" HAVING COUNT(*) >= $4
+        "#)
+            .bind(started as i64)
+            .bind(ended as i64)
+            .bind(requirement.word_count as i32)
+            .bind(requirement.message_count as i64)
+            .fetch_all(&mut connection)
+            .await?;
+
+        user_rows.iter().map(|user_row| Ok(user_row.try_get("sent_by")?)).collect()
+    }
+
+    async fn complete_induction_cycle(&self,
+                                      cycle: &InductionCycle, ended: u64,
+                                      qualifying_users: &[i64]) -> Result<Vec<i64>> {
+        let mut transaction = self.connection_pool.begin().await?;
+
+        let mut newly_inducted = Vec::new();
+        for user_id in qualifying_users {
+            let result = sqlx::query(r#"
+            INSERT INTO "inducted" ("user", "cycle") VALUES ($1, $2)
+              ON CONFLICT ("user") DO NOTHING
+            "#)
+                .bind(*user_id)
+                .bind(cycle.id)
+                .execute(&mut transaction)
+                .await?;
+            if result.rows_affected() > 0 {
+                newly_inducted.push(*user_id);
+            }
+        }
+        sqlx::query(r#"
+        UPDATE "induction_cycles" SET "ended" = $1, "qualified_count" = $2, "inducted_count" = $3
+          WHERE "id" = $4
+        "#)
+            .bind(ended as i64)
+            .bind(qualifying_users.len() as i32)
+            .bind(newly_inducted.len() as i32)
+            .bind(cycle.id)
+            .execute(&mut transaction)
+            .await?;
+        sqlx::query(r#"
+        INSERT INTO "induction_cycles" ("started") VALUES ($1)
+        "#).bind(ended as i64).execute(&mut transaction).await?;
+
+        transaction.commit().await?;
+        Ok(newly_inducted)
+    }
+
+    async fn count_messages(&self, user_id: i64, since: u64, min_score: u32) -> Result<u64> {
+        let mut connection = self.connection_pool.acquire().await?;
+
+        let count: i64 = sqlx::query(r#"
+        SELECT COUNT(*) AS "count" FROM "messages"
+          WHERE "sent_by" = $1 AND "created" >= $2 AND "score" >= $3
+            AND "counted"
+        "#)
+            .bind(user_id)
+            .bind(since as i64)
+            .bind(min_score as i32)
+            .fetch_one(&mut connection)
+            .await?
+            .try_get("count")?;
+
+        Ok(count as u64)
+    }
+
+    async fn set_discord_name(&self, discord_id: u64, discord_name: &str) -> Result<()> {
+        let mut connection = self.connection_pool.acquire().await?;
+
+        sqlx::query(r#"
+        INSERT INTO "users" ("discord_id", "discord_name") VALUES ($1, $2)
+          ON CONFLICT ("discord_id") DO UPDATE SET "discord_name" = EXCLUDED."discord_name"
+        "#).bind(discord_id as i64).bind(discord_name).execute(&mut connection).await?;
+
+        Ok(())
+    }
+
+    async fn leaderboard(&self,
+                         since: u64, until: u64,
+                         ranking: Ranking, limit: u32) -> Result<Vec<(User, u64)>> {
+        let mut connection = self.connection_pool.acquire().await?;
+
+        let query = match ranking {
+            Ranking::QualifyingMessages { min_score } => sqlx::query(r#"
+            SELECT "users"."id", "users"."discord_id", "users"."discord_name", "users"."irc_nickname",
+                COUNT(*) AS "score"
+              FROM "messages" INNER JOIN "users" ON "messages"."sent_by" = "users"."id"
+              WHERE "messages"."created" >= $1 AND "messages"."created" < $2 AND "messages"."counted"
+                AND "messages"."score" >= $3
+              GROUP BY "users"."id", "users"."discord_id", "users"."discord_name", "users"."irc_nickname"
+              ORDER BY "score" DESC LIMIT $4
+            "#).bind(since as i64).bind(until.min(i64::MAX as u64) as i64).bind(min_score as i32),
+            Ranking::TotalScore => sqlx::query(r#"
+            SELECT "users"."id", "users"."discord_id", "users"."discord_name", "users"."irc_nickname",
+                SUM("messages"."score") AS "score"
+              FROM "messages" INNER JOIN "users" ON "messages"."sent_by" = "users"."id"
+              WHERE "messages"."created" >= $1 AND "messages"."created" < $2 AND "messages"."counted"
+              GROUP BY "users"."id", "users"."discord_id", "users"."discord_name", "users"."irc_nickname"
+              ORDER BY "score" DESC LIMIT $3
+            "#).bind(since as i64).bind(until.min(i64::MAX as u64) as i64)
+        };
+        let ranked_rows = query.bind(limit as i64).fetch_all(&mut connection).await?;
+
+        ranked_rows.iter().map(|ranked_row| {
+            let score: i64 = ranked_row.try_get("score")?;
+            Ok((User::from_row(ranked_row)?, score as u64))
+        }).collect()
+    }
+
+    async fn users(&self, user_ids: &[i64]) -> Result<Vec<User>> {
+        let mut connection = self.connection_pool.acquire().await?;
+
+        let user_rows = sqlx::query(r#"
+        SELECT "id", "discord_id", "discord_name", "irc_nickname" FROM "users" WHERE "id" = ANY($1)
+        "#).bind(user_ids).fetch_all(&mut connection).await?;
+
+        user_rows.iter().map(User::from_row).collect()
+    }
+
+    async fn inducted_users(&self) -> Result<Vec<User>> {
+        let mut connection = self.connection_pool.acquire().await?;
+
+        let user_rows = sqlx::query(r#"
+        SELECT "users"."id", "users"."discord_id", "users"."discord_name", "users"."irc_nickname"
+          FROM "users" INNER JOIN "inducted" ON "inducted"."user" = "users"."id"
+        "#).fetch_all(&mut connection).await?;
+
+        user_rows.iter().map(User::from_row).collect()
+    }
+
+    async fn is_inducted(&self, user_identifier: UserIdentifier<'_>) -> Result<bool> {
+        let mut connection = self.connection_pool.acquire().await?;
+
+        let query = match user_identifier {
+            UserIdentifier::DiscordId(discord_id) => sqlx::query(r#"
+            SELECT 1 FROM "inducted" INNER JOIN "users" ON "inducted"."user" = "users"."id"
+              WHERE "users"."discord_id" = $1
+            "#).bind(discord_id as i64),
+            UserIdentifier::IrcNickname(irc_nickname) => sqlx::query(r#"
+            SELECT 1 FROM "inducted" INNER JOIN "users" ON "inducted"."user" = "users"."id"
+              WHERE "users"."irc_nickname" = $1
+            "#).bind(irc_nickname)
+        };
+        Ok(query.fetch_optional(&mut connection).await?.is_some())
+    }
+
+    async fn find_user(&self, user_identifier: UserIdentifier<'_>) -> Result<Option<i64>> {
+        let mut connection = self.connection_pool.acquire().await?;
+
+        let query = match user_identifier {
+            UserIdentifier::DiscordId(discord_id) => sqlx::query(r#"
+            SELECT "id" FROM "users" WHERE "discord_id" = $1
+            "#).bind(discord_id as i64),
+            UserIdentifier::IrcNickname(irc_nickname) => sqlx::query(r#"
+            SELECT "id" FROM "users" WHERE "irc_nickname" = $1
+            "#).bind(irc_nickname)
+        };
+        let user_row = query.fetch_optional(&mut connection).await?;
+        Ok(match user_row {
+            Some(user_row) => Some(user_row.try_get("id")?),
+            None => None
+        })
+    }
+
+    async fn induct(&self, user_id: i64) -> Result<bool> {
+        let mut connection = self.connection_pool.acquire().await?;
+
+        let result = sqlx::query(r#"
+        INSERT INTO "inducted" ("user") VALUES ($1)
+          ON CONFLICT ("user") DO NOTHING
+        "#).bind(user_id).execute(&mut connection).await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn revoke_induction(&self, user_id: i64) -> Result<bool> {
+        let mut connection = self.connection_pool.acquire().await?;
+
+        let result = sqlx::query(r#"
+        DELETE FROM "inducted" WHERE "user" = $1
+        "#).bind(user_id).execute(&mut connection).await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn create_link_code(&self, discord_id: u64, code: &str, expires: u64) -> Result<()> {
+        let mut transaction = self.connection_pool.begin().await?;
+
+        let discord_id = discord_id as i64;
+        sqlx::query(r#"
+        INSERT INTO "users" ("discord_id") VALUES ($1)
+          ON CONFLICT ("discord_id") DO NOTHING
+        "#).bind(discord_id).execute(&mut transaction).await?;
+        let user_id: i64 = sqlx::query(r#"
+        SELECT "id" FROM "users" WHERE "discord_id" = $1
+        "#).bind(discord_id).fetch_one(&mut transaction).await?.try_get("id")?;
+
+        sqlx::query(r#"
+        DELETE FROM "link_codes" WHERE "user" = $1
+        "#).bind(user_id).execute(&mut transaction).await?;
+        sqlx::query(r#"
+        INSERT INTO "link_codes" ("code", "user", "expires") VALUES ($1, $2, $3)
+        "#)
+            .bind(code)
+            .bind(user_id)
+            .bind(expires as i64)
+            .execute(&mut transaction)
+            .await?;
+
+        transaction.commit().await?;
+        Ok(())
+    }
+
+    async fn redeem_link_code(&self, code: &str, irc_nickname: &str,
+                              now: u64) -> Result<Redemption> {
+        let mut transaction = self.connection_pool.begin().await?;
+
+        let code_row = sqlx::query(r#"
+        DELETE FROM "link_codes" WHERE "code" = $1 RETURNING "user", "expires"
+        "#).bind(code).fetch_optional(&mut transaction).await?;
+        let (user_id, expires): (i64, i64) = match code_row {
+            Some(code_row) => (code_row.try_get("user")?, code_row.try_get("expires")?),
+            None => return Ok(Redemption::InvalidCode)
+        };
+        if expires as u64 <= now {
+            // Keep the deletion of the expired code
+            transaction.commit().await?;
+            return Ok(Redemption::InvalidCode);
+        }
+
+        let irc_user_row = sqlx::query(r#"
+        SELECT "id", "discord_id" FROM "users" WHERE "irc_nickname" = $1
+        "#).bind(irc_nickname).fetch_optional(&mut transaction).await?;
+        if let Some(irc_user_row) = irc_user_row {
+            let irc_user_id: i64 = irc_user_row.try_get("id")?;
+            let irc_user_discord_id: Option<i64> = irc_user_row.try_get("discord_id")?;
+            if irc_user_id != user_id && irc_user_discord_id.is_some() {
+                // The code remains valid, since the transaction is not committed
+                return Ok(Redemption::AlreadyLinked);
+            }
+            if irc_user_id != user_id {
+                sqlx::query(r#"
+                UPDATE "messages" SET "sent_by" = $1 WHERE "sent_by" = $2
+                "#).bind(user_id).bind(irc_user_id).execute(&mut transaction).await?;
+                sqlx::query(r#"
+                INSERT INTO "inducted" ("user", "cycle")
+                  SELECT $1, "cycle" FROM "inducted" WHERE "user" = $2
+                  ON CONFLICT ("user") DO NOTHING
+                "#).bind(user_id).bind(irc_user_id).execute(&mut transaction).await?;
+                sqlx::query(r#"
+                DELETE FROM "users" WHERE "id" = $1
+                "#).bind(irc_user_id).execute(&mut transaction).await?;
+            }
+        }
+        sqlx::query(r#"
+        UPDATE "users" SET "irc_nickname" = $1 WHERE "id" = $2
+        "#).bind(irc_nickname).bind(user_id).execute(&mut transaction).await?;
+
+        let is_inducted = sqlx::query(r#"
+        SELECT 1 FROM "inducted" WHERE "user" = $1
+        "#).bind(user_id).fetch_optional(&mut transaction).await?.is_some();
+
+        transaction.commit().await?;
+        Ok(Redemption::Linked(LinkedAccounts {
+            user_id,
+            inducted: is_inducted
+        }))
+    }
+
+    async fn add_faq_entry(&self, keywords: &[String], answer: &str, created: u64) -> Result<FaqEntry> {
+        let mut connection = self.connection_pool.acquire().await?;
+
+        let entry_row = sqlx::query(r#"
+        INSERT INTO "faq_entries" ("keywords", "answer", "created") VALUES ($1, $2, $3)
+          RETURNING "id", "keywords", "answer", "hits"
+        "#)
+            .bind(keywords.join(" "))
+            .bind(answer)
+            .bind(created as i64)
+            .fetch_one(&mut connection)
+            .await?;
+
+        FaqEntry::from_row(&entry_row)
+    }
+
+    async fn remove_faq_entry(&self, id: i64) -> Result<bool> {
+        let mut connection = self.connection_pool.acquire().await?;
+
+        let result = sqlx::query(r#"
+        DELETE FROM "faq_entries" WHERE "id" = $1
+        "#).bind(id).execute(&mut connection).await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn faq_entries(&self) -> Result<Vec<FaqEntry>> {
+        let mut connection = self.connection_pool.acquire().await?;
+
+        let entry_rows = sqlx::query(r#"
+        SELECT "id", "keywords", "answer", "hits" FROM "faq_entries" ORDER BY "id"
+        "#).fetch_all(&mut connection).await?;
+
+        entry_rows.iter().map(FaqEntry::from_row).collect()
+    }
+
+    async fn record_faq_hit(&self, id: i64) -> Result<()> {
+        let mut connection = self.connection_pool.acquire().await?;
+
+        sqlx::query(r#"
+        UPDATE "faq_entries" SET "hits" = "hits" + 1 WHERE "id" = $1
+        "#).bind(id).execute(&mut connection).await?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use eyre::Result;
+    use sqlx::Row;
+    use crate::brain::tokenizer::TokenCounts;
+    use crate::database::{RecordedMessage, UserIdentifier};
+    use crate::database::test_support::ThrowawayDatabase;
+
+    #[async_std::test]
+    #[ignore = "requires a Postgres server at TEST_POSTGRES_URL"]
+    async fn recorded_messages_are_persisted() -> Result<()> {
+        let throwaway = ThrowawayDatabase::create().await?;
+        let database = throwaway.database();
+        database.migrate().await?;
+        let message = |sender, word_count| RecordedMessage {
+            sender,
+            channel: Some("#general"),
+            guild_id: None,
+            message_id: None,
+            tokens: TokenCounts {
+                dictionary_words: word_count,
+                urls: 1,
+                ..TokenCounts::default()
+            },
+            char_count: 20,
+            counted: true,
+            score: word_count
+        };
+        database.record_message(&message(UserIdentifier::DiscordId(1234), 7)).await?;
+        database.record_message(&message(UserIdentifier::IrcNickname("A248"), 3)).await?;
+        database.record_message(&message(UserIdentifier::IrcNickname("A248"), 4)).await?;
+
+        // Read the tables directly, rather than through the storage being tested
+        let user_rows = sqlx::query(r#"
+        SELECT "discord_id", "irc_nickname" FROM "users" ORDER BY "id"
+        "#).fetch_all(&throwaway.connection_pool).await?;
+        let users = user_rows.iter().map(|user_row| {
+            Ok((user_row.try_get("discord_id")?, user_row.try_get("irc_nickname")?))
+        }).collect::<Result<Vec<(Option<i64>, Option<String>)>>>()?;
+        assert_eq!(vec![(Some(1234), None), (None, Some(String::from("A248")))], users);
+
+        let message_rows = sqlx::query(r#"
+        SELECT "messages"."word_count", "messages"."platform", "messages"."channel", "messages"."url_count"
+          FROM "messages" INNER JOIN "users" ON "messages"."sent_by" = "users"."id"
+          WHERE "users"."irc_nickname" IS NOT NULL ORDER BY "messages"."word_count"
+        "#).fetch_all(&throwaway.connection_pool).await?;
+        let messages = message_rows.iter().map(|message_row| {
+            Ok((
+                message_row.try_get("word_count")?, message_row.try_get("platform")?,
+                message_row.try_get("channel")?, message_row.try_get("url_count")?
+            ))
+        }).collect::<Result<Vec<(i32, String, String, i32)>>>()?;
+        let general = String::from("#general");
+        assert_eq!(vec![
+            (3, String::from("irc"), general.clone(), 1), (4, String::from("irc"), general, 1)
+        ], messages);
+
+        let message_count: i64 = sqlx::query(r#"
+        SELECT COUNT(*) AS "count" FROM "messages"
+        "#).fetch_one(&throwaway.connection_pool).await?.try_get("count")?;
+        assert_eq!(3, message_count);
+
+        Ok(())
+    }
+}
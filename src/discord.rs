@@ -17,13 +17,29 @@
  * and navigate to version 3 of the GNU General Public License.
  */
 
-use std::sync::Arc;
+mod slash_commands;
+
+use std::collections::{HashMap, HashSet};
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use async_std::channel::Receiver;
 use serenity::client::{Context, EventHandler};
+use serenity::builder::{CreateEmbed, CreateMessage};
+use serenity::http::Http;
 use async_trait::async_trait;
 use eyre::Result;
+use futures::future::{self, Either};
 use serenity::model::channel::Message;
-use serenity::model::id::UserId;
-use crate::database::{Database, UserIdentifier};
+use serenity::model::gateway::Ready;
+use serenity::model::id::{ChannelId, GuildId, MessageId, RoleId, UserId};
+use serenity::model::interactions::{Interaction, InteractionApplicationCommandCallbackDataFlags, InteractionResponseType};
+use serenity::model::interactions::application_command::{ApplicationCommand, ApplicationCommandInteraction};
+use crate::brain::{Brain, Embed, IncomingMessage, Response};
+use crate::brain::commands::Permission;
+use crate::database::{Database, RecordedMessage, UserIdentifier};
+use crate::identity::IdentityFilter;
+use crate::induction::InductionEvent;
+use crate::tracking::{Disposition, TrackingPolicy};
 use crate::ShutdownSignal;
 
 type DiscordClient = serenity::client::Client;
@@ -31,44 +47,192 @@ type DiscordClient = serenity::client::Client;
 #[derive(Debug)]
 pub struct DiscordBot {
     config: crate::config::DiscordBot,
-    database: Database
+    database: Database,
+    brain: Arc<Brain>,
+    tracking: TrackingPolicy,
+    identity_filter: IdentityFilter,
+    induction_events: Receiver<InductionEvent>
 }
 
 impl DiscordBot {
-    pub fn new(config: crate::config::DiscordBot, database: Database) -> Self {
+    pub fn new(config: crate::config::DiscordBot,
+               database: Database,
+               brain: Arc<Brain>,
+               tracking: TrackingPolicy,
+               identity_filter: IdentityFilter,
+               induction_events: Receiver<InductionEvent>) -> Self {
         Self {
             config,
-            database
+            database,
+            brain,
+            tracking,
+            identity_filter,
+            induction_events
         }
     }
 
     pub async fn start(self, shutdown_signal: Arc<ShutdownSignal>) -> Result<()> {
 
-        let mut client = DiscordClient::builder(self.config.bot_token)
+        let mut client_builder = DiscordClient::builder(self.config.bot_token)
             .event_handler(Handler {
-                database: self.database
-            })
-            .await?;
+                database: self.database.clone(),
+                brain: self.brain,
+                tracking: self.tracking,
+                identity_filter: self.identity_filter,
+                guild_id: self.config.guild_id,
+                slash_commands_enabled: self.config.application_id.is_some(),
+                bot_owners: self.config.bot_owners,
+                moderator_role_ids: self.config.moderator_role_ids,
+                known_names: Mutex::default()
+            });
+        if let Some(application_id) = self.config.application_id {
+            client_builder = client_builder.application_id(application_id);
+        }
+        let mut client = client_builder.await?;
 
         let shard_manager = client.shard_manager.clone();
+        let induction_effects = InductionEffects {
+            http: client.cache_and_http.http.clone(),
+            database: self.database,
+            guild_id: self.config.guild_id,
+            inducted_role_id: self.config.inducted_role_id,
+            leaderboard_channel_id: self.config.leaderboard_channel_id
+        };
+        let induction_events = self.induction_events;
 
-        let start_task = client.start();
-        let shutdown_task = async move {
-            shutdown_signal.await_shutdown().await;
+        let stop_client = async move {
             shard_manager.lock().await.shutdown_all().await;
-            Ok::<_, eyre::Report>(())
         };
+        run_client(
+            async { Ok(client.start().await?) }, induction_effects.synchronize(induction_events),
+            &shutdown_signal, stop_client
+        ).await
+    }
+}
+
+/// Runs the client alongside the induction effects until shutdown, then stops the client.
+/// Should the client stop or fail before shutdown, this returns at once so that the task
+/// may be restarted
+async fn run_client<C, E, S>(client: C, induction_effects: E,
+                             shutdown_signal: &ShutdownSignal, stop_client: S) -> Result<()>
+    where C: Future<Output = Result<()>>, E: Future<Output = Result<()>>, S: Future<Output = ()> {
+    let induction_effects = async {
+        induction_effects.await?;
+        // No more events will arrive, which is no reason to stop the client
+        future::pending().await
+    };
+    let running = future::select(Box::pin(client), Box::pin(induction_effects));
+    match future::select(running, Box::pin(shutdown_signal.await_shutdown())).await {
+        Either::Left((ended, _)) => ended.factor_first().0,
+        Either::Right((_, running)) => {
+            stop_client.await;
+            running.await.factor_first().0
+        }
+    }
+}
+
+/// Reflects induction events on discord: keeps the inducted role in line with the users
+/// recorded as inducted, and posts the leaderboard when each cycle completes
+struct InductionEffects {
+    http: Arc<Http>,
+    database: Database,
+    guild_id: u64,
+    inducted_role_id: Option<u64>,
+    leaderboard_channel_id: Option<u64>
+}
 
-        let (r1, r2) = futures::future::join(start_task, shutdown_task).await;
-        r1?;
-        r2?;
+impl InductionEffects {
+    async fn synchronize(self, induction_events: Receiver<InductionEvent>) -> Result<()> {
+        if let Some(role_id) = self.inducted_role_id {
+            if let Err(e) = self.reconcile(role_id).await {
+                log::error!("Failed to reconcile the inducted role with the database: {}", e);
+            }
+        }
+        while let Ok(induction_event) = induction_events.recv().await {
+            if let Err(e) = self.apply(induction_event).await {
+                log::error!("Failed to reflect induction event on discord: {}", e);
+            }
+        }
+        Ok(())
+    }
+
+    async fn apply(&self, induction_event: InductionEvent) -> Result<()> {
+        match (induction_event, self.inducted_role_id, self.leaderboard_channel_id) {
+            (InductionEvent::Granted(users), Some(role_id), _) => {
+                for discord_id in users.into_iter().filter_map(|user| user.discord_id) {
+                    if let Err(e) = self.http.add_member_role(self.guild_id, discord_id, role_id).await {
+                        log::error!("Failed to grant the inducted role to {}: {}", discord_id, e);
+                    }
+                }
+            }
+            (InductionEvent::Revoked(users), Some(role_id), _) => {
+                for discord_id in users.into_iter().filter_map(|user| user.discord_id) {
+                    if let Err(e) = self.http.remove_member_role(self.guild_id, discord_id, role_id).await {
+                        log::error!("Failed to remove the inducted role from {}: {}", discord_id, e);
+                    }
+                }
+            }
+            (InductionEvent::CycleCompleted { leaderboard }, _, Some(channel_id)) => {
+                ChannelId(channel_id).send_message(&self.http, |message| {
+                    message.set_embed(create_embed(&leaderboard))
+                }).await?;
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Repairs any drift between the guild's members and the database
+    async fn reconcile(&self, role_id: u64) -> Result<()> {
+        const PAGE_SIZE: u64 = 1000;
+
+        let inducted = self.database.inducted_users().await?
+            .into_iter()
+            .filter_map(|user| user.discord_id)
+            .collect::<HashSet<_>>();
+        let (mut granted, mut revoked) = (0, 0);
+        let mut after = None;
+        loop {
+            let members = self.http.get_guild_members(self.guild_id, Some(PAGE_SIZE), after).await?;
+            for member in &members {
+                let UserId(discord_id) = member.user.id;
+                let has_role = member.roles.contains(&RoleId(role_id));
+                let is_inducted = inducted.contains(&discord_id);
+                if is_inducted && !has_role {
+                    match self.http.add_member_role(self.guild_id, discord_id, role_id).await {
+                        Ok(()) => granted += 1,
+                        Err(e) => log::error!("Failed to grant the inducted role to {}: {}", discord_id, e)
+                    }
+                } else if !is_inducted && has_role {
+                    match self.http.remove_member_role(self.guild_id, discord_id, role_id).await {
+                        Ok(()) => revoked += 1,
+                        Err(e) => log::error!("Failed to remove the inducted role from {}: {}", discord_id, e)
+                    }
+                }
+            }
+            match members.last() {
+                Some(last) if members.len() as u64 == PAGE_SIZE => after = Some(last.user.id.0),
+                _ => break
+            }
+        }
+        log::info!("Reconciled the inducted role: granted to {}, removed from {}", granted, revoked);
         Ok(())
     }
 }
 
 #[derive(Debug)]
 struct Handler {
-    database: Database
+    database: Database,
+    brain: Arc<Brain>,
+    tracking: TrackingPolicy,
+    identity_filter: IdentityFilter,
+    guild_id: u64,
+    /// Whether an application ID is configured, without which slash commands cannot be registered
+    slash_commands_enabled: bool,
+    bot_owners: Vec<u64>,
+    moderator_role_ids: Vec<u64>,
+    /// The names already stored in the database, to avoid storing them repeatedly
+    known_names: Mutex<HashMap<u64, String>>
 }
 
 #[async_trait]
@@ -78,19 +242,212 @@ impl EventHandler for Handler {
             log::error!("Failed to handle discord message: {}", e);
         }
     }
+
+    async fn ready(&self, ctx: Context, _ready: Ready) {
+        if self.slash_commands_enabled {
+            if let Err(e) = self.register_slash_commands(&ctx).await {
+                log::error!("Failed to register slash commands: {}", e);
+            }
+        }
+    }
+
+    async fn interaction_create(&self, ctx: Context, interaction: Interaction) {
+        if let Interaction::ApplicationCommand(command) = interaction {
+            if let Err(e) = self.handle_slash_command(ctx, command).await {
+                log::error!("Failed to handle slash command: {}", e);
+            }
+        }
+    }
 }
 
 impl Handler {
-    async fn handle_message(&self, ctx: Context, message: Message) -> Result<()> {
-        if let Some(response) = crate::brain::respond_to_message(&message.content) {
-            message.reply(ctx, response).await?;
+    fn permission_of(&self, discord_id: u64, roles: &[RoleId]) -> Permission {
+        let is_moderator = roles.iter().any(|RoleId(role_id)| self.moderator_role_ids.contains(role_id));
+        if self.bot_owners.contains(&discord_id) {
+            Permission::Owner
+        } else if is_moderator {
+            Permission::Moderator
+        } else {
+            Permission::Member
+        }
+    }
+
+    async fn remember_name(&self, discord_id: u64, name: &str) -> Result<()> {
+        let is_known = self.known_names.lock().unwrap().get(&discord_id).map(String::as_str) == Some(name);
+        if !is_known {
+            self.database.set_discord_name(discord_id, name).await?;
+            self.known_names.lock().unwrap().insert(discord_id, name.to_string());
         }
+        Ok(())
+    }
+
+    async fn handle_message(&self, ctx: Context, message: Message) -> Result<()> {
         let UserId(discord_id) = message.author.id;
-        let content = message.content;
+        let sender = UserIdentifier::DiscordId(discord_id);
+        let own_user = ctx.cache.current_user().await;
+        let UserId(own_id) = own_user.id;
+        let is_webhook = message.webhook_id.is_some();
+        if let Err(skipped) = self.identity_filter.discord_sender(discord_id, message.author.bot, is_webhook, own_id) {
+            log::trace!("Skipped discord message from {}: {:?}", message.author.name, skipped);
+            return Ok(());
+        }
+        let ChannelId(channel_id) = message.channel_id;
+        // Discord shows channel mentions as the channel name
+        let channel_mention = format!("<#{}>", channel_id);
+        let own_names = [format!("<@{}>", own_id), format!("<@!{}>", own_id), own_user.name];
+        let incoming_message = IncomingMessage {
+            content: &message.content,
+            sender,
+            sender_name: &message.author.name,
+            channel: message.guild_id.map(|_| channel_mention.as_str()),
+            own_names: &own_names,
+            permission: self.permission_of(
+                discord_id, message.member.as_ref().map(|member| member.roles.as_slice()).unwrap_or_default()
+            ),
+            is_private: message.guild_id.is_none(),
+            irc_account: None
+        };
+        if let Some(response) = self.brain.respond_to_message(&incoming_message).await {
+            if response.private && !incoming_message.is_private {
+                message.author.direct_message(&ctx, |reply| create_message(reply, &response)).await?;
+            } else {
+                message.channel_id.send_message(&ctx, |reply| {
+                    create_message(reply, &response).reference_message(&message)
+                }).await?;
+            }
+        }
+        self.remember_name(discord_id, &message.author.name).await?;
 
-        self.database.record_message(
-            UserIdentifier::DiscordId(discord_id),
-            crate::brain::count_words(content)
-        ).await
+        let disposition = self.tracking.discord_message(
+            message.guild_id.map(|GuildId(guild_id)| guild_id), channel_id
+        );
+        if !disposition.is_recorded() {
+            return Ok(());
+        }
+        let channel_id = channel_id.to_string();
+        let MessageId(message_id) = message.id;
+        let message_id = message_id.to_string();
+        let channel = message.guild_id.map(|_| channel_id.as_str());
+        let assessment = self.brain.assess_message(sender, channel, &message.content);
+        let recorded_message = RecordedMessage {
+            channel,
+            guild_id: message.guild_id.map(|GuildId(guild_id)| guild_id),
+            message_id: Some(&message_id),
+            counted: disposition == Disposition::Counted,
+            ..RecordedMessage::new(sender, &message.content, assessment)
+        };
+        self.database.record_message(&recorded_message).await?;
+        Ok(())
+    }
+
+    /// Registers slash commands in the guild, or globally if no guild is configured
+    async fn register_slash_commands(&self, ctx: &Context) -> Result<()> {
+        if self.guild_id == 0 {
+            ApplicationCommand::set_global_application_commands(&ctx.http, slash_commands::define).await?;
+        } else {
+            GuildId(self.guild_id).set_application_commands(&ctx.http, slash_commands::define).await?;
+        }
+        log::info!("Registered slash commands");
+        Ok(())
+    }
+
+    async fn handle_slash_command(&self, ctx: Context, command: ApplicationCommandInteraction) -> Result<()> {
+        let UserId(discord_id) = command.user.id;
+        let sender = UserIdentifier::DiscordId(discord_id);
+        let arguments = slash_commands::arguments(&command.data);
+        let content = format!("/{} {}", command.data.name, arguments.join(" "));
+        let ChannelId(channel_id) = command.channel_id;
+        let channel_mention = format!("<#{}>", channel_id);
+        let incoming_message = IncomingMessage {
+            content: content.trim_end(),
+            sender,
+            sender_name: &command.user.name,
+            channel: command.guild_id.map(|_| channel_mention.as_str()),
+            own_names: &[],
+            permission: self.permission_of(
+                discord_id, command.member.as_ref().map(|member| member.roles.as_slice()).unwrap_or_default()
+            ),
+            is_private: command.guild_id.is_none(),
+            irc_account: None
+        };
+        let response = self.brain.execute_command(&command.data.name, &incoming_message, &arguments).await;
+        let response = match response {
+            Some(response) => response,
+            None => Response::private("That command is no longer available")
+        };
+        // Only the user who invoked the command sees the reply, sparing the channel
+        command.create_interaction_response(&ctx.http, |interaction_response| {
+            interaction_response
+                .kind(InteractionResponseType::ChannelMessageWithSource)
+                .interaction_response_data(|data| {
+                    if !response.content.is_empty() {
+                        data.content(&response.content);
+                    }
+                    data.embeds(response.embed.as_ref().map(create_embed))
+                        .flags(InteractionApplicationCommandCallbackDataFlags::EPHEMERAL)
+                })
+        }).await?;
+        self.remember_name(discord_id, &command.user.name).await?;
+        Ok(())
+    }
+}
+
+/// Fills in a message with the content and embed of a response
+fn create_message<'a, 'b>(message: &'b mut CreateMessage<'a>, response: &Response) -> &'b mut CreateMessage<'a> {
+    if !response.content.is_empty() {
+        message.content(&response.content);
+    }
+    if let Some(embed) = &response.embed {
+        message.set_embed(create_embed(embed));
+    }
+    message
+}
+
+fn create_embed(embed: &Embed) -> CreateEmbed {
+    let mut create_embed = CreateEmbed::default();
+    create_embed.title(&embed.title);
+    for (name, value) in &embed.fields {
+        create_embed.field(name, value, false);
+    }
+    if let Some(colour) = embed.colour {
+        create_embed.colour(colour);
+    }
+    if let Some(footer) = &embed.footer {
+        create_embed.footer(|create_footer| create_footer.text(footer));
+    }
+    create_embed
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use async_std::channel;
+    use super::*;
+
+    #[async_std::test]
+    async fn return_when_the_client_fails() {
+        let shutdown_signal = ShutdownSignal::default();
+        let client = async { Err(eyre::eyre!("The gateway refused the connection")) };
+        let run = run_client(client, future::pending(), &shutdown_signal, async {});
+        let result = async_std::future::timeout(Duration::from_secs(5), run).await
+            .expect("The client's failure should end the task");
+        assert!(result.is_err());
+        assert!(!shutdown_signal.is_shutdown());
+    }
+
+    #[async_std::test]
+    async fn stop_the_client_upon_shutdown() {
+        let shutdown_signal = ShutdownSignal::default();
+        let (stop, stopped) = channel::bounded::<()>(1);
+        let client = async move {
+            // Runs until stopped
+            let _ = stopped.recv().await;
+            Ok(())
+        };
+        shutdown_signal.commence_shutdown();
+        let run = run_client(client, future::pending(), &shutdown_signal, async move { stop.close(); });
+        let result = async_std::future::timeout(Duration::from_secs(5), run).await
+            .expect("Shutdown should stop the client");
+        assert!(result.is_ok());
     }
 }
@@ -0,0 +1,110 @@
+/*
+ * faithful-servant-bot
+ * Copyright © 2022 Anand Beh
+ *
+ * faithful-servant-bot is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * faithful-servant-bot is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with faithful-servant-bot. If not, see <https://www.gnu.org/licenses/>
+ * and navigate to version 3 of the GNU General Public License.
+ */
+
+use serenity::builder::{CreateApplicationCommand, CreateApplicationCommands};
+use serenity::model::interactions::application_command::{
+    ApplicationCommandInteractionData, ApplicationCommandInteractionDataOptionValue, ApplicationCommandOptionType
+};
+
+/// Defines every slash command. Each mirrors the text command of the same name
+pub fn define(commands: &mut CreateApplicationCommands) -> &mut CreateApplicationCommands {
+    commands
+        .create_application_command(|command| {
+            command.name("stats").description("Shows your progress toward induction in the current cycle")
+        })
+        .create_application_command(|command| {
+            command.name("status").description("Shows whether you are inducted, and your progress this cycle")
+        })
+        .create_application_command(define_leaderboard)
+        .create_application_command(|command| {
+            command.name("link").description("Obtains a code for linking your IRC nickname to your discord account")
+        })
+        .create_application_command(|command| {
+            define_user_command(command, "induct", "Inducts a user outside of the induction cycle")
+        })
+        .create_application_command(|command| {
+            define_user_command(command, "revoke", "Revokes a user's induction")
+        })
+}
+
+fn define_leaderboard(command: &mut CreateApplicationCommand) -> &mut CreateApplicationCommand {
+    command
+        .name("leaderboard")
+        .description("Shows the most active users")
+        .create_option(|option| {
+            option
+                .name("ranking")
+                .description("What users are ranked by")
+                .kind(ApplicationCommandOptionType::String)
+                .required(false)
+                .add_string_choice("Qualifying messages", "messages")
+                .add_string_choice("Total score", "score")
+        })
+        .create_option(|option| {
+            option
+                .name("period")
+                .description("The period of time covered")
+                .kind(ApplicationCommandOptionType::String)
+                .required(false)
+                .add_string_choice("Today", "day")
+                .add_string_choice("This week", "week")
+                .add_string_choice("This induction cycle", "cycle")
+                .add_string_choice("All time", "all")
+        })
+}
+
+fn define_user_command<'c>(command: &'c mut CreateApplicationCommand,
+                           name: &str, description: &str) -> &'c mut CreateApplicationCommand {
+    command
+        .name(name)
+        .description(description)
+        .create_option(|option| {
+            option
+                .name("user")
+                .description("The user")
+                .kind(ApplicationCommandOptionType::User)
+                .required(true)
+        })
+}
+
+/// Converts the options given to a slash command into text command arguments.
+/// Users become mentions, which text commands accept in place of names
+pub fn arguments(data: &ApplicationCommandInteractionData) -> Vec<String> {
+    data.options.iter()
+        .filter_map(|option| match &option.resolved {
+            Some(ApplicationCommandInteractionDataOptionValue::User(user, _)) => Some(format!("<@{}>", user.id.0)),
+            Some(ApplicationCommandInteractionDataOptionValue::String(value)) => Some(value.clone()),
+            Some(ApplicationCommandInteractionDataOptionValue::Integer(value)) => Some(value.to_string()),
+            _ => None
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn define_every_command() {
+        let mut commands = CreateApplicationCommands::default();
+        define(&mut commands);
+        let names = commands.0.iter().filter_map(|command| command["name"].as_str()).collect::<Vec<_>>();
+        assert_eq!(vec!["stats", "status", "leaderboard", "link", "induct", "revoke"], names);
+    }
+}
@@ -0,0 +1,268 @@
+/*
+ * faithful-servant-bot
+ * Copyright © 2022 Anand Beh
+ *
+ * faithful-servant-bot is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * faithful-servant-bot is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with faithful-servant-bot. If not, see <https://www.gnu.org/licenses/>
+ * and navigate to version 3 of the GNU General Public License.
+ */
+
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex, RwLock};
+use async_trait::async_trait;
+use eyre::Result;
+use crate::brain::{IncomingMessage, Response};
+use crate::brain::commands::{Command, Permission};
+use crate::brain::tokenizer::{Token, tokenize};
+use crate::config;
+use crate::database::{Database, FaqEntry};
+
+/// Answers messages resembling frequently asked questions. The entries are stored in the
+/// database and kept in memory for matching
+#[derive(Debug)]
+pub struct Faq {
+    config: config::Faq,
+    database: Database,
+    entries: RwLock<EntryCache>,
+    /// When each entry last answered in each channel
+    answered: Mutex<HashMap<(i64, String), u64>>
+}
+
+impl Faq {
+    pub fn new(config: config::Faq, database: Database) -> Self {
+        Self {
+            config,
+            database,
+            entries: RwLock::default(),
+            answered: Mutex::default()
+        }
+    }
+
+    async fn entries(&self) -> Result<Arc<Vec<FaqEntry>>> {
+        let generation = {
+            let cache = self.entries.read().unwrap();
+            if let Some(entries) = cache.entries.clone() {
+                return Ok(entries);
+            }
+            cache.generation
+        };
+        let entries = Arc::new(self.database.faq_entries().await?);
+        self.entries.write().unwrap().store(generation, entries.clone());
+        Ok(entries)
+    }
+
+    /// Adds an entry answering messages containing the keywords.
+    /// Returns `None` if the keywords contain no words
+    pub async fn add(&self, keywords: &str, answer: &str) -> Result<Option<FaqEntry>> {
+        let keywords = words_of(keywords);
+        if keywords.is_empty() {
+            return Ok(None);
+        }
+        let entry = self.database.add_faq_entry(&keywords, answer, crate::current_unix_time()).await?;
+        self.entries.write().unwrap().invalidate();
+        Ok(Some(entry))
+    }
+
+    pub async fn remove(&self, id: i64) -> Result<bool> {
+        let removed = self.database.remove_faq_entry(id).await?;
+        self.entries.write().unwrap().invalidate();
+        Ok(removed)
+    }
+
+    /// Answers the message with the entry whose keywords it best matches, if any
+    pub async fn respond(&self, message: &IncomingMessage<'_>) -> Result<Option<Response>> {
+        let entries = self.entries().await?;
+        let words = words_of(message.content).into_iter().collect::<HashSet<_>>();
+        let entry = match best_match(&entries, &words, self.config.min_matching_keywords as usize) {
+            Some(entry) => entry,
+            None => return Ok(None)
+        };
+        let now = crate::current_unix_time();
+        let place = message.channel.unwrap_or(message.sender_name).to_string();
+        {
+            let mut answered = self.answered.lock().unwrap();
+            let cooldown = self.config.cooldown_seconds;
+            if matches!(answered.get(&(entry.id, place.clone())), Some(time) if time + cooldown > now) {
+                return Ok(None);
+            }
+            answered.retain(|_, time| *time + cooldown > now);
+            answered.insert((entry.id, place), now);
+        }
+        self.database.record_faq_hit(entry.id).await?;
+        Ok(Some(Response::public(entry.answer.clone())))
+    }
+}
+
+/// The entries kept in memory. Each change to the entries begins a new generation, so that
+/// entries read from the database before a change are not kept after it
+#[derive(Debug, Default)]
+struct EntryCache {
+    generation: u64,
+    /// `None` until the entries are first needed, and whenever they change
+    entries: Option<Arc<Vec<FaqEntry>>>
+}
+
+impl EntryCache {
+    fn invalidate(&mut self) {
+        self.generation += 1;
+        self.entries = None;
+    }
+
+    /// Keeps entries read during the given generation, unless they have since changed
+    fn store(&mut self, generation: u64, entries: Arc<Vec<FaqEntry>>) {
+        if generation == self.generation {
+            self.entries = Some(entries);
+        }
+    }
+}
+
+/// The distinct words in the text, in lowercase
+fn words_of(text: &str) -> Vec<String> {
+    let mut seen = HashSet::new();
+    tokenize(text).into_iter()
+        .filter_map(|token| match token {
+            Token::Word(word) => Some(word),
+            _ => None
+        })
+        .filter(|word| seen.insert(word.clone()))
+        .collect()
+}
+
+/// The entry matching the greatest proportion of its keywords, breaking ties by the number
+/// of keywords matched
+fn best_match<'e>(entries: &'e [FaqEntry], words: &HashSet<String>,
+                  min_matching_keywords: usize) -> Option<&'e FaqEntry> {
+    entries.iter()
+        .filter(|entry| !entry.keywords.is_empty())
+        .map(|entry| {
+            let matching = entry.keywords.iter().filter(|keyword| words.contains(*keyword)).count();
+            (entry, matching)
+        })
+        .filter(|(entry, matching)| *matching >= min_matching_keywords.clamp(1, entry.keywords.len()))
+        .max_by(|(entry, matching), (other, other_matching)| {
+            let proportion = matching * other.keywords.len();
+            let other_proportion = other_matching * entry.keywords.len();
+            match proportion.cmp(&other_proportion) {
+                Ordering::Equal => matching.cmp(other_matching),
+                ordering => ordering
+            }
+        })
+        .map(|(entry, _)| entry)
+}
+
+/// Lets moderators manage FAQ entries
+#[derive(Debug)]
+pub struct FaqCommand {
+    faq: Arc<Faq>,
+    database: Database
+}
+
+impl FaqCommand {
+    pub fn new(faq: Arc<Faq>, database: Database) -> Self {
+        Self {
+            faq,
+            database
+        }
+    }
+}
+
+#[async_trait]
+impl Command for FaqCommand {
+    fn name(&self) -> &'static str {
+        "faq"
+    }
+
+    fn usage(&self) -> &'static str {
+        "add \"<keywords>\" <answer> | remove <id> | list"
+    }
+
+    fn help(&self) -> &'static str {
+        "Manages the answers given to messages containing the keywords of frequently asked questions"
+    }
+
+    fn permission(&self) -> Permission {
+        Permission::Moderator
+    }
+
+    async fn execute(&self, _message: &IncomingMessage<'_>, arguments: &[String]) -> Result<Response> {
+        let response = match arguments {
+            [action, keywords, answer @ ..] if action == "add" && !answer.is_empty() => {
+                match self.faq.add(keywords, &answer.join(" ")).await? {
+                    Some(entry) => format!("Added FAQ entry #{} for: {}", entry.id, entry.keywords.join(", ")),
+                    None => String::from("The keywords must contain at least one word")
+                }
+            }
+            [action, id] if action == "remove" => match id.parse() {
+                Ok(id) if self.faq.remove(id).await? => format!("Removed FAQ entry #{}", id),
+                Ok(id) => format!("There is no FAQ entry #{}", id),
+                Err(_) => format!("Not an entry number: {}", id)
+            },
+            [action] if action == "list" => {
+                let entries = self.database.faq_entries().await?;
+                if entries.is_empty() {
+                    String::from("There are no FAQ entries")
+                } else {
+                    entries.iter()
+                        .map(|entry| format!("#{} [{}] used {} times: {}",
+                                             entry.id, entry.keywords.join(", "), entry.hits, entry.answer))
+                        .collect::<Vec<_>>()
+                        .join("\n")
+                }
+            }
+            _ => format!("Usage: faq {}", self.usage())
+        };
+        Ok(Response::private(response))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(id: i64, keywords: &str) -> FaqEntry {
+        FaqEntry {
+            id,
+            keywords: words_of(keywords),
+            answer: String::new(),
+            hits: 0
+        }
+    }
+
+    fn best(entries: &[FaqEntry], content: &str) -> Option<i64> {
+        let words = words_of(content).into_iter().collect();
+        best_match(entries, &words, 2).map(|entry| entry.id)
+    }
+
+    #[test]
+    fn match_by_keyword_overlap() {
+        let entries = vec![entry(1, "join irc server"), entry(2, "irc nickname register"), entry(3, "rules")];
+        assert_eq!(Some(1), best(&entries, "How do I join the IRC server?"));
+        assert_eq!(Some(2), best(&entries, "how can I register my nickname on irc"));
+        // One keyword is not enough, unless the entry has only one
+        assert_eq!(None, best(&entries, "is anyone on irc"));
+        assert_eq!(Some(3), best(&entries, "where are the rules"));
+    }
+
+    #[test]
+    fn discard_entries_read_before_a_change() {
+        let mut cache = EntryCache::default();
+        let generation = cache.generation;
+        cache.invalidate();
+        cache.store(generation, Arc::new(vec![entry(1, "rules")]));
+        assert!(cache.entries.is_none());
+
+        cache.store(cache.generation, Arc::new(vec![entry(1, "rules")]));
+        assert!(cache.entries.is_some());
+    }
+}
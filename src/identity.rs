@@ -0,0 +1,241 @@
+/*
+ * faithful-servant-bot
+ * Copyright © 2022 Anand Beh
+ *
+ * faithful-servant-bot is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * faithful-servant-bot is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with faithful-servant-bot. If not, see <https://www.gnu.org/licenses/>
+ * and navigate to version 3 of the GNU General Public License.
+ */
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use async_trait::async_trait;
+use eyre::Result;
+use crate::brain::{IncomingMessage, Response};
+use crate::brain::commands::{Command, Permission};
+use crate::config::{Live, Tracking};
+
+/// IRC services, which speak on behalf of the network rather than any person
+const IRC_SERVICES: &[&str] = &[
+    "NickServ", "ChanServ", "MemoServ", "OperServ", "HostServ", "BotServ", "SaslServ", "Global", "Alis"
+];
+
+/// Why a sender's messages are neither answered nor recorded
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Skipped {
+    /// The bot itself
+    Myself,
+    /// A discord bot account
+    Bot,
+    /// A discord webhook
+    Webhook,
+    /// An IRC service, such as NickServ
+    Service,
+    /// An IRC server
+    Server,
+    /// A sender on the configured ignore list
+    Ignored
+}
+
+impl Skipped {
+    const ALL: [Skipped; 6] = [Self::Myself, Self::Bot, Self::Webhook, Self::Service, Self::Server, Self::Ignored];
+
+    fn describe(&self) -> &'static str {
+        match self {
+            Self::Myself => "from myself",
+            Self::Bot => "from bots",
+            Self::Webhook => "from webhooks",
+            Self::Service => "from IRC services",
+            Self::Server => "from IRC servers",
+            Self::Ignored => "from ignored users"
+        }
+    }
+}
+
+/// How many messages were skipped for each reason since startup
+#[derive(Debug, Default)]
+pub struct SkipCounters {
+    counts: [AtomicU64; Skipped::ALL.len()]
+}
+
+impl SkipCounters {
+    fn increment(&self, skipped: Skipped) {
+        self.counts[skipped as usize].fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn get(&self, skipped: Skipped) -> u64 {
+        self.counts[skipped as usize].load(Ordering::Relaxed)
+    }
+}
+
+#[derive(Debug)]
+struct IgnoreList {
+    irc_nicknames: Vec<String>,
+    discord_ids: Vec<u64>
+}
+
+impl IgnoreList {
+    fn new(config: &Tracking) -> Self {
+        Self {
+            irc_nicknames: config.ignored_irc_nicknames.clone(),
+            discord_ids: config.ignored_discord_ids.clone()
+        }
+    }
+}
+
+/// Filters out automated senders and the ignore list. Cloning is cheap, and clones share
+/// the ignore list and counters
+#[derive(Clone, Debug)]
+pub struct IdentityFilter {
+    ignored: Live<IgnoreList>,
+    counters: Arc<SkipCounters>
+}
+
+impl IdentityFilter {
+    pub fn new(config: &Tracking) -> Self {
+        Self {
+            ignored: Live::new(IgnoreList::new(config)),
+            counters: Arc::default()
+        }
+    }
+
+    /// Replaces the ignore list
+    pub fn update(&self, config: &Tracking) {
+        self.ignored.set(IgnoreList::new(config));
+    }
+
+    pub fn counters(&self) -> &Arc<SkipCounters> {
+        &self.counters
+    }
+
+    /// Checks the sender of an IRC message, given its nickname and hostname
+    pub fn irc_sender(&self, nickname: &str, hostname: &str, own_nickname: &str) -> Result<(), Skipped> {
+        let skipped = if nickname.eq_ignore_ascii_case(own_nickname) {
+            Some(Skipped::Myself)
+        } else if IRC_SERVICES.iter().any(|service| service.eq_ignore_ascii_case(nickname))
+            || hostname.starts_with("services.") {
+            Some(Skipped::Service)
+        } else if self.ignored.get().irc_nicknames.iter().any(|ignored| ignored.eq_ignore_ascii_case(nickname)) {
+            Some(Skipped::Ignored)
+        } else {
+            None
+        };
+        self.count(skipped)
+    }
+
+    /// Notes a message sent by an IRC server rather than a user
+    pub fn skip_irc_server(&self) {
+        self.counters.increment(Skipped::Server);
+    }
+
+    /// Checks the sender of a discord message
+    pub fn discord_sender(&self, author_id: u64, is_bot: bool, is_webhook: bool,
+                          own_id: u64) -> Result<(), Skipped> {
+        let skipped = if author_id == own_id {
+            Some(Skipped::Myself)
+        } else if is_webhook {
+            Some(Skipped::Webhook)
+        } else if is_bot {
+            Some(Skipped::Bot)
+        } else if self.ignored.get().discord_ids.contains(&author_id) {
+            Some(Skipped::Ignored)
+        } else {
+            None
+        };
+        self.count(skipped)
+    }
+
+    fn count(&self, skipped: Option<Skipped>) -> Result<(), Skipped> {
+        match skipped {
+            Some(skipped) => {
+                self.counters.increment(skipped);
+                Err(skipped)
+            }
+            None => Ok(())
+        }
+    }
+}
+
+/// Reports how many messages the identity filter skipped
+#[derive(Debug)]
+pub struct SkippedCommand {
+    counters: Arc<SkipCounters>
+}
+
+impl SkippedCommand {
+    pub fn new(counters: Arc<SkipCounters>) -> Self {
+        Self {
+            counters
+        }
+    }
+}
+
+#[async_trait]
+impl Command for SkippedCommand {
+    fn name(&self) -> &'static str {
+        "skipped"
+    }
+
+    fn help(&self) -> &'static str {
+        "Shows how many messages were not recorded since startup because of who sent them"
+    }
+
+    fn permission(&self) -> Permission {
+        Permission::Moderator
+    }
+
+    async fn execute(&self, _message: &IncomingMessage<'_>, _arguments: &[String]) -> Result<Response> {
+        let counts = Skipped::ALL.iter()
+            .map(|skipped| format!("{} {}", self.counters.get(*skipped), skipped.describe()))
+            .collect::<Vec<_>>();
+        Ok(Response::private(format!("Messages skipped since startup: {}", counts.join(", "))))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filter() -> IdentityFilter {
+        IdentityFilter::new(&Tracking {
+            ignored_irc_nicknames: vec![String::from("Spammer")],
+            ignored_discord_ids: vec![42],
+            ..Tracking::default()
+        })
+    }
+
+    #[test]
+    fn irc_senders() {
+        let filter = filter();
+        assert_eq!(Ok(()), filter.irc_sender("A248", "user/a248", "servant"));
+        assert_eq!(Err(Skipped::Myself), filter.irc_sender("Servant", "host", "servant"));
+        assert_eq!(Err(Skipped::Service), filter.irc_sender("nickserv", "services.", "servant"));
+        assert_eq!(Err(Skipped::Service), filter.irc_sender("Sigyn", "services.libera.chat", "servant"));
+        assert_eq!(Err(Skipped::Ignored), filter.irc_sender("spammer", "host", "servant"));
+        assert_eq!(1, filter.counters().get(Skipped::Myself));
+        assert_eq!(2, filter.counters().get(Skipped::Service));
+    }
+
+    #[test]
+    fn discord_senders() {
+        let filter = filter();
+        assert_eq!(Ok(()), filter.discord_sender(1, false, false, 100));
+        assert_eq!(Err(Skipped::Myself), filter.discord_sender(100, true, false, 100));
+        assert_eq!(Err(Skipped::Webhook), filter.discord_sender(2, true, true, 100));
+        assert_eq!(Err(Skipped::Bot), filter.discord_sender(3, true, false, 100));
+        assert_eq!(Err(Skipped::Ignored), filter.discord_sender(42, false, false, 100));
+        // Clones share their counters
+        filter.clone().discord_sender(3, true, false, 100).unwrap_err();
+        assert_eq!(2, filter.counters().get(Skipped::Bot));
+    }
+}
@@ -0,0 +1,322 @@
+/*
+ * faithful-servant-bot
+ * Copyright © 2022 Anand Beh
+ *
+ * faithful-servant-bot is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * faithful-servant-bot is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with faithful-servant-bot. If not, see <https://www.gnu.org/licenses/>
+ * and navigate to version 3 of the GNU General Public License.
+ */
+
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use async_std::channel::{self, Receiver, Sender};
+use async_trait::async_trait;
+use eyre::Result;
+use crate::brain::{Embed, IncomingMessage, Response};
+use crate::brain::commands::{self, Command, Permission};
+use crate::config::{Induction, Live};
+use crate::database::{Database, InductionCycle, User};
+use crate::leaderboard::Leaderboard;
+use crate::ShutdownSignal;
+
+pub const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+/// The length of each induction cycle, in seconds
+pub fn cycle_length(config: &Induction) -> Result<u64> {
+    match config.induction_cycle_days {
+        0 => Err(eyre::eyre!("induction_cycle_days must be at least 1")),
+        days => Ok(days as u64 * SECONDS_PER_DAY)
+    }
+}
+
+/// Periodically inducts the users who satisfy every message requirement.
+/// Cycles are recorded in the database, so the schedule survives restarts
+#[derive(Debug)]
+pub struct InductionEngine {
+    config: Live<Induction>,
+    database: Database,
+    inductions: Inductions,
+    leaderboard: Leaderboard
+}
+
+impl InductionEngine {
+    pub fn new(config: Live<Induction>, database: Database, inductions: Inductions) -> Self {
+        let leaderboard = Leaderboard::new(config.clone(), database.clone());
+        Self {
+            config,
+            database,
+            inductions,
+            leaderboard
+        }
+    }
+
+    pub async fn start(self, shutdown_signal: Arc<ShutdownSignal>) -> Result<()> {
+        loop {
+            let cycle_length = cycle_length(&self.config.get())?;
+            let cycle = match self.database.open_induction_cycle().await? {
+                Some(cycle) => cycle,
+                None => {
+                    let cycle = self.database.begin_induction_cycle(crate::current_unix_time()).await?;
+                    log::info!("Began the first induction cycle (#{})", cycle.id);
+                    cycle
+                }
+            };
+            let ends = cycle.started + cycle_length;
+            let now = crate::current_unix_time();
+            if now < ends {
+                let until_end = Duration::from_secs(ends - now);
+                // If the shutdown arrives first, the timeout does not elapse
+                if async_std::future::timeout(until_end, shutdown_signal.await_shutdown()).await.is_ok() {
+                    return Ok(());
+                }
+            }
+            self.run_cycle(&cycle, ends).await?;
+        }
+    }
+
+    async fn run_cycle(&self, cycle: &InductionCycle, ended: u64) -> Result<()> {
+        let config = self.config.get();
+        let requirements = &config.message_requirements;
+        if requirements.is_empty() {
+            log::warn!("No message requirements are configured, so nobody will be inducted");
+        }
+        let mut users_per_requirement = Vec::with_capacity(requirements.len());
+        for requirement in requirements {
+            users_per_requirement.push(
+                self.database.users_meeting_requirement(cycle.started, ended, requirement).await?
+            );
+        }
+        let qualifying_users = qualifying_users(users_per_requirement);
+        let newly_inducted = self.database.complete_induction_cycle(
+            cycle, ended, &qualifying_users
+        ).await?;
+
+        log::info!(
+            "Completed induction cycle #{} (from {} to {}): {} users qualified, {} newly inducted",
+            cycle.id, cycle.started, ended, qualifying_users.len(), newly_inducted.len()
+        );
+        self.inductions.announce_granted(&newly_inducted).await?;
+
+        let leaderboard = self.leaderboard.render_between(
+            &format!("Final leaderboard for induction cycle #{}", cycle.id), None, cycle.started, ended
+        ).await?;
+        self.inductions.publish(InductionEvent::CycleCompleted { leaderboard });
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum InductionEvent {
+    Granted(Vec<User>),
+    Revoked(Vec<User>),
+    CycleCompleted {
+        /// The rendered leaderboard for the completed cycle
+        leaderboard: Embed
+    }
+}
+
+/// Changes to users' induction status, which are announced to every subscriber.
+/// Cloning this struct is cheap as it simply increments a reference counter
+#[derive(Clone, Debug)]
+pub struct Inductions {
+    database: Database,
+    subscribers: Arc<Mutex<Vec<Sender<InductionEvent>>>>
+}
+
+impl Inductions {
+    pub fn new(database: Database) -> Self {
+        Self {
+            database,
+            subscribers: Arc::default()
+        }
+    }
+
+    /// Subscribes to induction events. Subscribers which drop their receiver are forgotten
+    pub fn subscribe(&self) -> Receiver<InductionEvent> {
+        let (sender, receiver) = channel::unbounded();
+        self.subscribers.lock().unwrap().push(sender);
+        receiver
+    }
+
+    fn publish(&self, event: InductionEvent) {
+        self.subscribers.lock().unwrap().retain(|subscriber| {
+            // Sending to an unbounded channel fails only if the channel is closed
+            subscriber.try_send(event.clone()).is_ok()
+        });
+    }
+
+    /// Announces that the given users, who are already recorded as inducted, were inducted
+    pub async fn announce_granted(&self, user_ids: &[i64]) -> Result<()> {
+        if !user_ids.is_empty() {
+            let users = self.database.users(user_ids).await?;
+            self.publish(InductionEvent::Granted(users));
+        }
+        Ok(())
+    }
+
+    /// Inducts a user. Returns false if the user was already inducted
+    pub async fn grant(&self, user_id: i64) -> Result<bool> {
+        let granted = self.database.induct(user_id).await?;
+        if granted {
+            self.announce_granted(&[user_id]).await?;
+        }
+        Ok(granted)
+    }
+
+    /// Revokes a user's induction. Returns false if the user was not inducted
+    pub async fn revoke(&self, user_id: i64) -> Result<bool> {
+        let revoked = self.database.revoke_induction(user_id).await?;
+        if revoked {
+            let users = self.database.users(&[user_id]).await?;
+            self.publish(InductionEvent::Revoked(users));
+        }
+        Ok(revoked)
+    }
+}
+
+/// Inducts a user by hand
+#[derive(Debug)]
+pub struct InductCommand {
+    database: Database,
+    inductions: Inductions
+}
+
+impl InductCommand {
+    pub fn new(database: Database, inductions: Inductions) -> Self {
+        Self {
+            database,
+            inductions
+        }
+    }
+}
+
+#[async_trait]
+impl Command for InductCommand {
+    fn name(&self) -> &'static str {
+        "induct"
+    }
+
+    fn usage(&self) -> &'static str {
+        "<user>"
+    }
+
+    fn help(&self) -> &'static str {
+        "Inducts a user, regardless of whether they meet the message requirements"
+    }
+
+    fn permission(&self) -> Permission {
+        Permission::Moderator
+    }
+
+    async fn execute(&self, _message: &IncomingMessage<'_>, arguments: &[String]) -> Result<Response> {
+        let user = match arguments.first() {
+            Some(user) => user,
+            None => return Ok(Response::private("Please specify a user"))
+        };
+        let user_id = match self.database.find_user(commands::parse_user(user)).await? {
+            Some(user_id) => user_id,
+            None => return Ok(Response::private(format!("Unknown user: {}", user)))
+        };
+        let response = if self.inductions.grant(user_id).await? {
+            format!("Inducted {}", user)
+        } else {
+            format!("{} is already inducted", user)
+        };
+        Ok(Response::public(response))
+    }
+}
+
+/// Revokes a user's induction
+#[derive(Debug)]
+pub struct RevokeCommand {
+    database: Database,
+    inductions: Inductions
+}
+
+impl RevokeCommand {
+    pub fn new(database: Database, inductions: Inductions) -> Self {
+        Self {
+            database,
+            inductions
+        }
+    }
+}
+
+#[async_trait]
+impl Command for RevokeCommand {
+    fn name(&self) -> &'static str {
+        "revoke"
+    }
+
+    fn usage(&self) -> &'static str {
+        "<user>"
+    }
+
+    fn help(&self) -> &'static str {
+        "Revokes a user's induction"
+    }
+
+    fn permission(&self) -> Permission {
+        Permission::Moderator
+    }
+
+    async fn execute(&self, _message: &IncomingMessage<'_>, arguments: &[String]) -> Result<Response> {
+        let user = match arguments.first() {
+            Some(user) => user,
+            None => return Ok(Response::private("Please specify a user"))
+        };
+        let user_id = match self.database.find_user(commands::parse_user(user)).await? {
+            Some(user_id) => user_id,
+            None => return Ok(Response::private(format!("Unknown user: {}", user)))
+        };
+        let response = if self.inductions.revoke(user_id).await? {
+            format!("Revoked the induction of {}", user)
+        } else {
+            format!("{} is not inducted", user)
+        };
+        Ok(Response::public(response))
+    }
+}
+
+/// Determines the users who meet every requirement, given the users meeting each requirement
+fn qualifying_users(users_per_requirement: Vec<Vec<i64>>) -> Vec<i64> {
+    let mut users_per_requirement = users_per_requirement.into_iter();
+    let mut qualifying = match users_per_requirement.next() {
+        Some(users) => users.into_iter().collect::<HashSet<_>>(),
+        None => return Vec::new()
+    };
+    for users in users_per_requirement {
+        let users = users.into_iter().collect::<HashSet<_>>();
+        qualifying.retain(|user| users.contains(user));
+    }
+    let mut qualifying = qualifying.into_iter().collect::<Vec<_>>();
+    qualifying.sort_unstable();
+    qualifying
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn no_requirements_qualifies_nobody() {
+        assert!(qualifying_users(Vec::new()).is_empty());
+    }
+
+    #[test]
+    fn must_meet_every_requirement() {
+        let users_per_requirement = vec![vec![1, 2, 3, 5], vec![5, 3, 4], vec![3, 5, 6]];
+        assert_eq!(vec![3, 5], qualifying_users(users_per_requirement));
+    }
+}
@@ -17,64 +17,252 @@
  * and navigate to version 3 of the GNU General Public License.
  */
 
+mod reconnect;
+mod replies;
+
+use std::collections::{HashMap, HashSet};
+use std::future::Future;
+use std::pin::Pin;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
+use async_std::channel::{self, Receiver, Sender};
 use eyre::Result;
 use futures::StreamExt;
+use futures::future::{self, Either};
 use irc::client::ClientStream;
-use irc::proto::{Command, Prefix};
-use crate::database::{Database, UserIdentifier};
+use irc::proto::{Capability, ChannelMode, Command, Mode, Prefix, Response};
+use irc::proto::message::Tag;
+use crate::brain::{Assessment, Brain, IncomingMessage};
+use crate::brain::commands::Permission;
+use crate::config::Live;
+use crate::database::{Database, RecordedMessage, UserIdentifier};
+use crate::identity::IdentityFilter;
+use crate::induction::InductionEvent;
+use crate::tracking::{Disposition, TrackingPolicy};
 use crate::ShutdownSignal;
+use self::reconnect::{Backoff, ConnectionState};
+use self::replies::{Kind, PacedLines, Replies};
 
 type IrcConfig = crate::config::IrcServer;
 type IrcClient = irc::client::Client;
 
+/// How long to wait for the server to close the connection after the bot quits
+const QUIT_TIMEOUT: Duration = Duration::from_secs(5);
+
 #[derive(Debug)]
 pub struct IrcBot {
-    irc_client: irc::client::Client,
-    database: Database
+    config: IrcConfig,
+    database: Database,
+    brain: Arc<Brain>,
+    tracking: TrackingPolicy,
+    identity_filter: IdentityFilter,
+    bot_channels: BotChannels,
+    inducted_mode: Option<Arc<InductedMode>>,
+    induction_events: Receiver<InductionEvent>,
+    /// Lines waiting to be sent are kept across reconnections
+    replies: Replies,
+    paced_lines: PacedLines,
+    state: ConnectionState
+}
+
+/// Why a connection ended
+enum SessionEnd {
+    Shutdown,
+    Disconnected(Option<eyre::Report>)
 }
 
 impl IrcBot {
-    pub async fn new(config: IrcConfig,
-                     database: Database) -> Result<Self> {
-
-        let irc_client = IrcClient::from_config(irc::client::data::Config {
-            owners: config.bot_owners,
-            nickname: Some(config.bot_username),
-            nick_password: Some(config.bot_password),
-            server: Some(config.host),
+    pub fn new(config: IrcConfig,
+               database: Database,
+               brain: Arc<Brain>,
+               tracking: TrackingPolicy,
+               identity_filter: IdentityFilter,
+               bot_channels: BotChannels,
+               induction_events: Receiver<InductionEvent>) -> Self {
+        let inducted_mode = config.inducted_channel_mode.map(|mode| Arc::new(InductedMode {
+            database: database.clone(),
+            channels: bot_channels.clone(),
+            mode
+        }));
+        let (replies, paced_lines) = Replies::new();
+        Self {
+            config,
+            database,
+            brain,
+            tracking,
+            identity_filter,
+            bot_channels,
+            inducted_mode,
+            induction_events,
+            replies,
+            paced_lines,
+            state: ConnectionState::Disconnected
+        }
+    }
+
+    fn transition(&mut self, state: ConnectionState) {
+        log::info!("IRC connection: {} -> {}", self.state, state);
+        self.state = state;
+    }
+
+    /// Stays connected until shutdown, reconnecting whenever the connection is lost
+    pub async fn start(mut self, shutdown_signal: Arc<ShutdownSignal>) -> Result<()> {
+        let mut backoff = Backoff::new(&self.config.reconnect);
+        let mut attempt = 1;
+        loop {
+            self.transition(ConnectionState::Connecting { attempt });
+            let connected_at = Instant::now();
+            match self.run_session(&shutdown_signal).await {
+                Ok(SessionEnd::Shutdown) => break,
+                Ok(SessionEnd::Disconnected(None)) => log::warn!("The IRC server closed the connection"),
+                Ok(SessionEnd::Disconnected(Some(e))) => log::warn!("Lost the IRC connection: {}", e),
+                Err(e) => log::warn!("Failed to connect to IRC: {}", e)
+            }
+            self.transition(ConnectionState::Disconnected);
+            if connected_at.elapsed() >= reconnect::STABLE_CONNECTION {
+                backoff.reset();
+                attempt = 1;
+            } else {
+                attempt += 1;
+            }
+            let delay = backoff.next_delay();
+            log::info!("Reconnecting to IRC in {} seconds", delay.as_secs());
+            let sleep = Box::pin(async_std::task::sleep(delay));
+            let shutdown = Box::pin(shutdown_signal.await_shutdown());
+            if let Either::Right(_) = future::select(sleep, shutdown).await {
+                break;
+            }
+        }
+        self.transition(ConnectionState::Stopped);
+        Ok(())
+    }
+
+    /// Connects, identifies and joins the bot channels, then handles messages until the
+    /// connection ends
+    async fn run_session(&mut self, shutdown_signal: &Arc<ShutdownSignal>) -> Result<SessionEnd> {
+        let config = &self.config;
+        let mut irc_client = IrcClient::from_config(irc::client::data::Config {
+            owners: config.bot_owners.clone(),
+            nickname: Some(config.bot_username.clone()),
+            nick_password: Some(config.bot_password.clone()),
+            server: Some(config.host.clone()),
             port: Some(config.port),
             use_tls: Some(true),
             encoding: Some(String::from("UTF-8")),
-            channels: config.bot_channels,
+            channels: self.bot_channels.current().to_vec(),
             ..irc::client::data::Config::default()
         }).await?;
-        Ok(IrcBot {
-            irc_client,
-            database,
-        })
+        // Requested anew with each session. Messages are then tagged with their native IDs,
+        // which are recorded, and with the services account of their sender. Each is requested
+        // alone, since the server refuses a request as a whole if it lacks any capability in it
+        for capability in [Capability::Custom("message-tags"), Capability::AccountTag] {
+            irc_client.send_cap_req(&[capability])?;
+        }
+        irc_client.identify()?;
+        let message_stream = irc_client.stream()?;
+        let irc_client = Arc::new(irc_client);
+        self.transition(ConnectionState::Connected);
+
+        let reception = MessageReceiver {
+            message_stream,
+            database: self.database.clone(),
+            brain: self.brain.clone(),
+            tracking: self.tracking.clone(),
+            identity_filter: self.identity_filter.clone(),
+            bot_owners: self.config.bot_owners.clone(),
+            bot_moderators: self.config.bot_moderators.clone(),
+            irc_client: irc_client.clone(),
+            replies: self.replies.clone(),
+            inducted_mode: self.inducted_mode.clone(),
+            shutdown_signal: shutdown_signal.clone(),
+            channel_names: HashMap::new()
+        }.receive_messages();
+        let induction_effects = async {
+            reflect_induction_events(
+                &irc_client, &self.replies, self.inducted_mode.as_deref(),
+                self.config.leaderboard_channel.as_deref(), &self.induction_events
+            ).await?;
+            // No more events will arrive, which is no reason to disconnect
+            future::pending().await
+        };
+        let pacing = self.paced_lines.pace(&irc_client, &self.config.flood_protection);
+        let channel_changes = self.bot_channels.follow_changes(&irc_client);
+
+        // Whichever activity ends first ends the connection
+        let activities: Vec<Pin<Box<dyn Future<Output = Result<()>> + Send + '_>>> = vec![
+            Box::pin(reception), Box::pin(induction_effects), Box::pin(pacing), Box::pin(channel_changes)
+        ];
+        let shutdown = Box::pin(shutdown_signal.await_shutdown());
+        let end = match future::select(future::select_all(activities), shutdown).await {
+            Either::Left(((result, _, _), _)) => Ok(SessionEnd::Disconnected(result.err())),
+            Either::Right((_, activities)) => {
+                irc_client.send_quit("Goodbye")?;
+                // Lines are written only while the message stream is polled, so reception
+                // continues until the server closes the connection
+                let _ = async_std::future::timeout(QUIT_TIMEOUT, activities).await;
+                Ok(SessionEnd::Shutdown)
+            }
+        };
+        end
     }
+}
 
-    pub async fn start(mut self, shutdown_signal: Arc<ShutdownSignal>) -> Result<()> {
-        log::info!("Connecting to IRC...");
-        self.irc_client.identify()?;
+/// The channels the bot sits in, which may change while it is connected.
+/// Cloning is cheap, and clones share the channels
+#[derive(Clone, Debug)]
+pub struct BotChannels {
+    current: Live<Vec<String>>,
+    /// Changes not yet made on the server
+    changes: (Sender<ChannelChange>, Receiver<ChannelChange>)
+}
 
-        let message_stream = self.irc_client.stream()?;
-        let irc_client = Arc::new(self.irc_client);
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ChannelChange {
+    pub joined: Vec<String>,
+    pub parted: Vec<String>
+}
 
-        let reception_future = MessageReceiver {
-            message_stream,
-            database: self.database,
-            irc_client: irc_client.clone()
-        }.receive_messages();
-        let shutdown_future = async move {
-            shutdown_signal.await_shutdown().await;
-            irc_client.send_quit("Goodbye")
+impl BotChannels {
+    pub fn new(channels: Vec<String>) -> Self {
+        Self {
+            current: Live::new(channels),
+            changes: channel::unbounded()
+        }
+    }
+
+    fn current(&self) -> Arc<Vec<String>> {
+        self.current.get()
+    }
+
+    fn contains(channels: &[String], channel: &str) -> bool {
+        channels.iter().any(|listed| listed.eq_ignore_ascii_case(channel))
+    }
+
+    /// Switches to the given channels, which are joined and parted as soon as the bot
+    /// is connected. Yields the difference from the previous channels
+    pub fn update(&self, channels: Vec<String>) -> ChannelChange {
+        let current = self.current();
+        let change = ChannelChange {
+            joined: channels.iter().filter(|channel| !Self::contains(&current, channel)).cloned().collect(),
+            parted: current.iter().filter(|channel| !Self::contains(&channels, channel)).cloned().collect()
         };
+        self.current.set(channels);
+        if change != ChannelChange::default() {
+            // The receiver is never dropped
+            let _ = self.changes.0.try_send(change.clone());
+        }
+        change
+    }
 
-        let (r1, r2) = futures::future::join(reception_future, shutdown_future).await;
-        r1?;
-        r2?;
+    async fn follow_changes(&self, irc_client: &IrcClient) -> Result<()> {
+        while let Ok(change) = self.changes.1.recv().await {
+            for channel in &change.joined {
+                irc_client.send_join(channel)?;
+            }
+            for channel in &change.parted {
+                irc_client.send_part(channel)?;
+            }
+        }
         Ok(())
     }
 }
@@ -83,7 +271,17 @@ impl IrcBot {
 struct MessageReceiver {
     message_stream: ClientStream,
     database: Database,
-    irc_client: Arc<IrcClient>
+    brain: Arc<Brain>,
+    tracking: TrackingPolicy,
+    identity_filter: IdentityFilter,
+    bot_owners: Vec<String>,
+    bot_moderators: Vec<String>,
+    irc_client: Arc<IrcClient>,
+    replies: Replies,
+    inducted_mode: Option<Arc<InductedMode>>,
+    shutdown_signal: Arc<ShutdownSignal>,
+    /// Channel member lists which are still being received
+    channel_names: HashMap<String, Vec<String>>
 }
 
 impl MessageReceiver {
@@ -91,29 +289,71 @@ impl MessageReceiver {
 
         while let Some(irc_message) = self.message_stream.next().await.transpose()? {
 
-            if let Some(Prefix::Nickname(nickname, _, _)) = irc_message.prefix {
+            // Once the bot has quit, messages are read only to keep the stream flowing
+            if self.shutdown_signal.is_shutdown() {
+                continue;
+            }
+
+            if let Some(inducted_mode) = self.inducted_mode.clone() {
+                self.observe_channel_members(&inducted_mode, &irc_message.prefix, &irc_message.command);
+            }
+
+            let is_message = matches!(irc_message.command, Command::PRIVMSG(..) | Command::NOTICE(..));
+            let sender = match irc_message.prefix {
+                Some(Prefix::Nickname(nickname, _, hostname)) if is_message => {
+                    let own_nickname = self.irc_client.current_nickname();
+                    match self.identity_filter.irc_sender(&nickname, &hostname, own_nickname) {
+                        Ok(()) => Some(nickname),
+                        Err(skipped) => {
+                            log::trace!("Skipped IRC message from {}: {:?}", nickname, skipped);
+                            None
+                        }
+                    }
+                }
+                Some(Prefix::ServerName(_)) if is_message => {
+                    self.identity_filter.skip_irc_server();
+                    None
+                }
+                _ => None
+            };
+            if let Some(nickname) = sender {
 
                 // 1. Respond to the message
                 // 2. Record the message
 
-                let content = match irc_message.command {
+                // Servers supporting message tags identify each message
+                let message_id = tag_value(&irc_message.tags, "msgid");
+                let (target, content) = match irc_message.command {
                     Command::PRIVMSG(target, content) => {
                         // Respond only to PRIVMSG per the IRC protocol
                         // NOTICE commands should not be responded to
-                        if let Some(response) = crate::brain::respond_to_message(&content) {
-                            self.irc_client.send(Command::NOTICE(target, response.into_owned()))?;
-                        }
-                        content
+                        let account = tag_value(&irc_message.tags, "account");
+                        self.respond(nickname.clone(), account, target.clone(), content.clone());
+                        (target, content)
                     },
-                    Command::NOTICE(_, content) => content,
+                    Command::NOTICE(target, content) => (target, content),
                     _ => continue
                 };
+                let channel = if target == self.irc_client.current_nickname() { None } else { Some(target) };
+                let disposition = self.tracking.irc_message(channel.as_deref());
+                if !disposition.is_recorded() {
+                    continue;
+                }
+                let assessment = self.brain.assess_message(
+                    UserIdentifier::IrcNickname(&nickname), channel.as_deref(), &content
+                );
                 let message_handle = MessageHandle {
                     database: self.database.clone(),
                     nickname,
-                    content
+                    channel,
+                    message_id,
+                    content,
+                    counted: disposition == Disposition::Counted,
+                    assessment
                 };
+                let write = self.shutdown_signal.begin_write();
                 async_std::task::spawn(async move {
+                    let _write = write;
                     if let Err(e) = message_handle.handle().await {
                         log::error!("Error handling IRC message: {}", e)
                     }
@@ -122,21 +362,263 @@ impl MessageReceiver {
         }
         Ok(())
     }
+
+    fn respond(&self, nickname: String, account: Option<String>, target: String, content: String) {
+        let brain = self.brain.clone();
+        let irc_client = self.irc_client.clone();
+        let replies = self.replies.clone();
+        let permission = permission_of(&self.bot_owners, &self.bot_moderators, account.as_deref());
+        async_std::task::spawn(async move {
+            let is_private = target == irc_client.current_nickname();
+            let own_names = [irc_client.current_nickname().to_string()];
+            let incoming_message = IncomingMessage {
+                content: &content,
+                sender: UserIdentifier::IrcNickname(&nickname),
+                sender_name: &nickname,
+                channel: if is_private { None } else { Some(target.as_str()) },
+                own_names: &own_names,
+                permission,
+                is_private,
+                irc_account: account.as_deref()
+            };
+            if let Some(response) = brain.respond_to_message(&incoming_message).await {
+                replies.reply(irc_client.current_nickname(), &nickname, &target, &response);
+            }
+        });
+    }
+
+    /// Collects channel member lists and notices joining users, so that the inducted mode
+    /// can be reconciled with the database
+    fn observe_channel_members(&mut self,
+                               inducted_mode: &Arc<InductedMode>,
+                               prefix: &Option<Prefix>,
+                               command: &Command) {
+        match (prefix, command) {
+            (_, Command::Response(Response::RPL_NAMREPLY, args)) => {
+                if let [_, _, channel, names] = args.as_slice() {
+                    self.channel_names.entry(channel.clone()).or_default()
+                        .extend(names.split_whitespace().map(String::from));
+                }
+            }
+            (_, Command::Response(Response::RPL_ENDOFNAMES, args)) => {
+                if let Some(channel) = args.get(1) {
+                    let names = self.channel_names.remove(channel).unwrap_or_default();
+                    let inducted_mode = inducted_mode.clone();
+                    let irc_client = self.irc_client.clone();
+                    let channel = channel.clone();
+                    async_std::task::spawn(async move {
+                        if let Err(e) = inducted_mode.reconcile(&irc_client, &channel, names).await {
+                            log::error!("Failed to reconcile the inducted mode in {}: {}", channel, e)
+                        }
+                    });
+                }
+            }
+            (Some(Prefix::Nickname(nickname, _, _)), Command::JOIN(channel, _, _)) => {
+                let inducted_mode = inducted_mode.clone();
+                let irc_client = self.irc_client.clone();
+                let (nickname, channel) = (nickname.clone(), channel.clone());
+                async_std::task::spawn(async move {
+                    if let Err(e) = inducted_mode.on_join(&irc_client, &channel, &nickname).await {
+                        log::error!("Failed to apply the inducted mode to {} in {}: {}", nickname, channel, e)
+                    }
+                });
+            }
+            _ => {}
+        }
+    }
+}
+
+/// The value of a message tag, if the server sent it
+fn tag_value(tags: &Option<Vec<Tag>>, key: &str) -> Option<String> {
+    tags.as_ref()?.iter()
+        .find(|Tag(tag_key, _)| tag_key == key)
+        .and_then(|Tag(_, value)| value.clone())
+}
+
+/// Permissions are granted by services account, since anyone may take an unused nickname.
+/// Senders not identified to an account are members
+fn permission_of(bot_owners: &[String], bot_moderators: &[String], account: Option<&str>) -> Permission {
+    let is_listed = |accounts: &[String]| {
+        matches!(account, Some(account) if accounts.iter().any(|listed| listed.eq_ignore_ascii_case(account)))
+    };
+    if is_listed(bot_owners) {
+        Permission::Owner
+    } else if is_listed(bot_moderators) {
+        Permission::Moderator
+    } else {
+        Permission::Member
+    }
+}
+
+/// Reflects induction events on IRC: applies the inducted mode, and posts the leaderboard
+/// when each cycle completes
+async fn reflect_induction_events(irc_client: &IrcClient,
+                                  replies: &Replies,
+                                  inducted_mode: Option<&InductedMode>,
+                                  leaderboard_channel: Option<&str>,
+                                  induction_events: &Receiver<InductionEvent>) -> Result<()> {
+    while let Ok(induction_event) = induction_events.recv().await {
+        match (induction_event, inducted_mode, leaderboard_channel) {
+            (InductionEvent::Granted(users), Some(inducted_mode), _) => {
+                for nickname in users.iter().filter_map(|user| user.irc_nickname.as_deref()) {
+                    inducted_mode.set_mode_everywhere(irc_client, nickname, true);
+                }
+            }
+            (InductionEvent::Revoked(users), Some(inducted_mode), _) => {
+                for nickname in users.iter().filter_map(|user| user.irc_nickname.as_deref()) {
+                    inducted_mode.set_mode_everywhere(irc_client, nickname, false);
+                }
+            }
+            (InductionEvent::CycleCompleted { leaderboard }, _, Some(leaderboard_channel)) => {
+                for line in leaderboard.lines() {
+                    replies.send(Kind::Privmsg, leaderboard_channel, &line);
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// Keeps the inducted channel mode in line with the users recorded as inducted
+#[derive(Debug)]
+struct InductedMode {
+    database: Database,
+    channels: BotChannels,
+    mode: char
+}
+
+impl InductedMode {
+    fn channel_mode(&self) -> ChannelMode {
+        match self.mode {
+            'q' => ChannelMode::Founder,
+            'a' => ChannelMode::Admin,
+            'o' => ChannelMode::Oper,
+            'h' => ChannelMode::Halfop,
+            'v' => ChannelMode::Voice,
+            mode => ChannelMode::Unknown(mode)
+        }
+    }
+
+    /// The prefix which marks users having the mode in channel member lists
+    fn member_prefix(&self) -> Option<char> {
+        match self.mode {
+            'q' => Some('~'),
+            'a' => Some('&'),
+            'o' => Some('@'),
+            'h' => Some('%'),
+            'v' => Some('+'),
+            _ => None
+        }
+    }
+
+    fn is_bot_channel(&self, channel: &str) -> bool {
+        BotChannels::contains(&self.channels.current(), channel)
+    }
+
+    fn set_mode(&self, irc_client: &IrcClient, channel: &str, nickname: &str, grant: bool) -> Result<()> {
+        let mode = if grant {
+            Mode::Plus(self.channel_mode(), Some(nickname.to_string()))
+        } else {
+            Mode::Minus(self.channel_mode(), Some(nickname.to_string()))
+        };
+        irc_client.send(Command::ChannelMODE(channel.to_string(), vec![mode]))?;
+        Ok(())
+    }
+
+    /// Sets the mode in each bot channel. A failure in one channel is logged, and does not
+    /// keep the mode from being set in the others
+    fn set_mode_everywhere(&self, irc_client: &IrcClient, nickname: &str, grant: bool) {
+        for channel in self.channels.current().iter() {
+            if let Err(e) = self.set_mode(irc_client, channel, nickname, grant) {
+                log::error!("Failed to set the inducted mode for {} in {}: {}", nickname, channel, e);
+            }
+        }
+    }
+
+    async fn on_join(&self, irc_client: &IrcClient, channel: &str, nickname: &str) -> Result<()> {
+        if self.is_bot_channel(channel)
+            && self.database.is_inducted(UserIdentifier::IrcNickname(nickname)).await? {
+            self.set_mode(irc_client, channel, nickname, true)?;
+        }
+        Ok(())
+    }
+
+    /// Repairs any drift between a channel's member list and the database
+    async fn reconcile(&self, irc_client: &IrcClient, channel: &str, names: Vec<String>) -> Result<()> {
+        if !self.is_bot_channel(channel) {
+            return Ok(());
+        }
+        let member_prefix = match self.member_prefix() {
+            Some(member_prefix) => member_prefix,
+            None => {
+                log::warn!("Cannot reconcile channel mode {} since member lists do not show it", self.mode);
+                return Ok(());
+            }
+        };
+        let inducted = self.database.inducted_users().await?
+            .into_iter()
+            .filter_map(|user| user.irc_nickname)
+            .collect::<HashSet<_>>();
+        let (mut granted, mut revoked) = (0, 0);
+        for name in names {
+            let nickname = name.trim_start_matches(|c| "~&@%+".contains(c));
+            if nickname == irc_client.current_nickname() {
+                continue;
+            }
+            let has_mode = name[..name.len() - nickname.len()].contains(member_prefix);
+            let is_inducted = inducted.contains(nickname);
+            let grant = is_inducted && !has_mode;
+            if grant || (!is_inducted && has_mode) {
+                match self.set_mode(irc_client, channel, nickname, grant) {
+                    Ok(()) if grant => granted += 1,
+                    Ok(()) => revoked += 1,
+                    Err(e) => log::error!("Failed to set the inducted mode for {} in {}: {}", nickname, channel, e)
+                }
+            }
+        }
+        log::info!("Reconciled the inducted mode in {}: granted to {}, removed from {}", channel, granted, revoked);
+        Ok(())
+    }
 }
 
 #[derive(Debug)]
 struct MessageHandle {
     database: Database,
     nickname: String,
-    content: String
+    /// The channel, or `None` for a private message
+    channel: Option<String>,
+    message_id: Option<String>,
+    content: String,
+    counted: bool,
+    assessment: Assessment
 }
 
 impl MessageHandle {
     async fn handle(self) -> Result<()> {
-        self.database.record_message(
-            UserIdentifier::IrcNickname(&self.nickname),
-            crate::brain::count_words(self.content)
-        ).await
+        let recorded_message = RecordedMessage {
+            channel: self.channel.as_deref(),
+            message_id: self.message_id.as_deref(),
+            counted: self.counted,
+            ..RecordedMessage::new(UserIdentifier::IrcNickname(&self.nickname), &self.content, self.assessment)
+        };
+        self.database.record_message(&recorded_message).await?;
+        Ok(())
     }
 }
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn permissions_follow_services_accounts() {
+        let owners = [String::from("A248")];
+        let moderators = [String::from("Helper")];
+        assert_eq!(Permission::Owner, permission_of(&owners, &moderators, Some("a248")));
+        assert_eq!(Permission::Moderator, permission_of(&owners, &moderators, Some("HELPER")));
+        assert_eq!(Permission::Member, permission_of(&owners, &moderators, Some("Someone")));
+        assert_eq!(Permission::Member, permission_of(&owners, &moderators, None));
+    }
+}
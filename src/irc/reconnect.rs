@@ -0,0 +1,105 @@
+/*
+ * faithful-servant-bot
+ * Copyright © 2022 Anand Beh
+ *
+ * faithful-servant-bot is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * faithful-servant-bot is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with faithful-servant-bot. If not, see <https://www.gnu.org/licenses/>
+ * and navigate to version 3 of the GNU General Public License.
+ */
+
+use std::fmt::{self, Display, Formatter};
+use std::time::Duration;
+use rand::Rng;
+use crate::config::Reconnect;
+
+/// A connection which lasted this long is considered healthy, so the backoff starts over
+pub const STABLE_CONNECTION: Duration = Duration::from_secs(60);
+
+/// Delays between attempts to reconnect, doubling with each failure up to a limit.
+/// Each delay is randomised between half and all of its value, so that many clients
+/// disconnected at once do not reconnect in step
+#[derive(Debug)]
+pub struct Backoff {
+    initial: Duration,
+    max: Duration,
+    failures: u32
+}
+
+impl Backoff {
+    pub fn new(config: &Reconnect) -> Self {
+        Self {
+            initial: Duration::from_secs(config.initial_delay_seconds),
+            max: Duration::from_secs(config.max_delay_seconds),
+            failures: 0
+        }
+    }
+
+    pub fn next_delay(&mut self) -> Duration {
+        let base = self.base_delay();
+        self.failures = self.failures.saturating_add(1);
+        let half = base / 2;
+        half + half.mul_f64(rand::thread_rng().gen_range(0.0..=1.0))
+    }
+
+    fn base_delay(&self) -> Duration {
+        self.initial.checked_mul(2u32.saturating_pow(self.failures))
+            .map_or(self.max, |delay| delay.min(self.max))
+    }
+
+    pub fn reset(&mut self) {
+        self.failures = 0;
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ConnectionState {
+    Connecting { attempt: u32 },
+    Connected,
+    /// Waiting to reconnect
+    Disconnected,
+    Stopped
+}
+
+impl Display for ConnectionState {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Connecting { attempt } => write!(f, "connecting (attempt {})", attempt),
+            Self::Connected => f.write_str("connected"),
+            Self::Disconnected => f.write_str("disconnected"),
+            Self::Stopped => f.write_str("stopped")
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn delays_double_up_to_the_limit() {
+        let mut backoff = Backoff::new(&Reconnect {
+            initial_delay_seconds: 2,
+            max_delay_seconds: 10
+        });
+        let bases = (0..5).map(|_| {
+            let base = backoff.base_delay();
+            let delay = backoff.next_delay();
+            assert!(delay >= base / 2 && delay <= base);
+            base.as_secs()
+        }).collect::<Vec<_>>();
+        assert_eq!(vec![2, 4, 8, 10, 10], bases);
+
+        backoff.reset();
+        assert_eq!(Duration::from_secs(2), backoff.base_delay());
+    }
+}
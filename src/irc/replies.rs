@@ -0,0 +1,190 @@
+/*
+ * faithful-servant-bot
+ * Copyright © 2022 Anand Beh
+ *
+ * faithful-servant-bot is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * faithful-servant-bot is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with faithful-servant-bot. If not, see <https://www.gnu.org/licenses/>
+ * and navigate to version 3 of the GNU General Public License.
+ */
+
+use std::time::{Duration, Instant};
+use async_std::channel::{self, Receiver, Sender};
+use async_std::task;
+use eyre::Result;
+use crate::brain::Response;
+use crate::config::FloodProtection;
+use super::IrcClient;
+
+/// The most bytes in a line, including the trailing CRLF
+const MAX_LINE_BYTES: usize = 512;
+/// Servers prefix relayed lines with the sender's nick!user@host, whose length is unknown here
+const PREFIX_ALLOWANCE: usize = 100;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Kind {
+    Privmsg,
+    Notice
+}
+
+impl Kind {
+    fn command(&self) -> &'static str {
+        match self {
+            Self::Privmsg => "PRIVMSG",
+            Self::Notice => "NOTICE"
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Line {
+    kind: Kind,
+    target: String,
+    text: String
+}
+
+/// Queues lines to be sent at a pace which the server tolerates. Cloning this struct
+/// is cheap, and every clone feeds the same queue
+#[derive(Clone, Debug)]
+pub struct Replies {
+    queue: Sender<Line>
+}
+
+impl Replies {
+    /// Creates the queue, along with its receiving end which must be passed to `pace`
+    pub fn new() -> (Self, PacedLines) {
+        let (queue, lines) = channel::unbounded();
+        (Self { queue }, PacedLines { lines })
+    }
+
+    /// Answers a message sent by `sender` to `target`. Queries are answered by PRIVMSG to the
+    /// sender. Messages in channels are answered by NOTICE, to the sender alone if the
+    /// response is private
+    pub fn reply(&self, own_nickname: &str, sender: &str, target: &str, response: &Response) {
+        let (kind, reply_to) = route(own_nickname, sender, target, response.private);
+        for line in response.lines() {
+            self.send(kind, reply_to, &line);
+        }
+    }
+
+    /// Queues the text, split into as many lines as needed
+    pub fn send(&self, kind: Kind, target: &str, text: &str) {
+        let max_bytes = MAX_LINE_BYTES - PREFIX_ALLOWANCE
+            - "\r\n".len() - kind.command().len() - " ".len() - target.len() - " :".len();
+        for piece in split_line(text, max_bytes) {
+            let line = Line {
+                kind,
+                target: target.to_string(),
+                text: piece.to_string()
+            };
+            if self.queue.try_send(line).is_err() {
+                log::warn!("Dropped a line to {} since the IRC connection is closed", target);
+                return;
+            }
+        }
+    }
+}
+
+/// The receiving end of the queue
+#[derive(Debug)]
+pub struct PacedLines {
+    lines: Receiver<Line>
+}
+
+impl PacedLines {
+    /// Sends queued lines until every `Replies` is dropped, or until the connection fails.
+    /// Up to `burst_lines` lines are sent at once, after which one line is sent per interval
+    pub async fn pace(&self, irc_client: &IrcClient, flood_protection: &FloodProtection) -> Result<()> {
+        let interval = Duration::from_millis(flood_protection.line_interval_millis);
+        let allowance = interval * flood_protection.burst_lines;
+        // Each line sent pushes this further ahead, and it falls back as time passes
+        let mut penalty = Instant::now();
+        while let Ok(line) = self.lines.recv().await {
+            let now = Instant::now();
+            penalty = penalty.max(now);
+            let ahead = penalty - now;
+            if ahead > allowance {
+                task::sleep(ahead - allowance).await;
+            }
+            match line.kind {
+                Kind::Privmsg => irc_client.send_privmsg(&line.target, &line.text)?,
+                Kind::Notice => irc_client.send_notice(&line.target, &line.text)?
+            }
+            penalty += interval;
+        }
+        Ok(())
+    }
+}
+
+fn route<'m>(own_nickname: &str, sender: &'m str, target: &'m str, private: bool) -> (Kind, &'m str) {
+    if target.eq_ignore_ascii_case(own_nickname) {
+        (Kind::Privmsg, sender)
+    } else if private {
+        (Kind::Notice, sender)
+    } else {
+        (Kind::Notice, target)
+    }
+}
+
+/// Splits text into pieces of at most `max_bytes`, preferably between words.
+/// Line breaks always split the text. A lone carriage return is a line break too, since the
+/// IRC server would otherwise take it as the end of the command
+fn split_line(text: &str, max_bytes: usize) -> Vec<&str> {
+    let mut pieces = Vec::new();
+    for line in text.split(['\n', '\r']) {
+        let mut rest = line.trim_end();
+        while rest.len() > max_bytes {
+            let mut end = max_bytes;
+            while !rest.is_char_boundary(end) {
+                end -= 1;
+            }
+            let split = match rest[..end].rfind(' ') {
+                Some(space) if space > 0 => space,
+                _ => end
+            };
+            pieces.push(&rest[..split]);
+            rest = rest[split..].trim_start();
+        }
+        if !rest.is_empty() {
+            pieces.push(rest);
+        }
+    }
+    pieces
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn route_replies() {
+        assert_eq!((Kind::Privmsg, "A248"), route("Servant", "A248", "servant", false));
+        assert_eq!((Kind::Privmsg, "A248"), route("Servant", "A248", "Servant", true));
+        assert_eq!((Kind::Notice, "#chat"), route("Servant", "A248", "#chat", false));
+        assert_eq!((Kind::Notice, "A248"), route("Servant", "A248", "#chat", true));
+    }
+
+    #[test]
+    fn split_long_lines() {
+        assert_eq!(vec!["one two", "three"], split_line("one two\n\nthree\n", 20));
+        assert_eq!(vec!["one two", "three four"], split_line("one two three four", 10));
+        assert_eq!(vec!["abcd", "efgh", "ij"], split_line("abcdefghij", 4));
+        // Multibyte characters are not divided
+        assert_eq!(vec!["é", "é"], split_line("éé", 3));
+    }
+
+    #[test]
+    fn split_on_carriage_returns() {
+        assert_eq!(vec!["one", "two", "three"], split_line("one\rtwo\r\nthree\r", 20));
+        assert!(split_line("\r\n\r", 20).is_empty());
+    }
+}
@@ -0,0 +1,243 @@
+/*
+ * faithful-servant-bot
+ * Copyright © 2022 Anand Beh
+ *
+ * faithful-servant-bot is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * faithful-servant-bot is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with faithful-servant-bot. If not, see <https://www.gnu.org/licenses/>
+ * and navigate to version 3 of the GNU General Public License.
+ */
+
+use async_trait::async_trait;
+use eyre::Result;
+use crate::brain::{Embed, IncomingMessage, Response};
+use crate::brain::commands::Command;
+use crate::config::{Induction, Live};
+use crate::database::{Database, Ranking};
+use crate::induction::SECONDS_PER_DAY;
+
+const LEADERBOARD_SIZE: u32 = 10;
+/// Gold
+const LEADERBOARD_COLOUR: u32 = 0xF1C40F;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Window {
+    Day,
+    Week,
+    Cycle,
+    AllTime
+}
+
+/// Ranks users by their activity
+#[derive(Clone, Debug)]
+pub struct Leaderboard {
+    config: Live<Induction>,
+    database: Database
+}
+
+impl Leaderboard {
+    pub fn new(config: Live<Induction>, database: Database) -> Self {
+        Self {
+            config,
+            database
+        }
+    }
+
+    /// Qualifying messages are those meeting the smallest word requirement
+    fn qualifying_messages(&self) -> Ranking {
+        let min_score = self.config.get().message_requirements.iter()
+            .map(|requirement| requirement.word_count as u32)
+            .min()
+            .unwrap_or(0);
+        Ranking::QualifyingMessages { min_score }
+    }
+
+    /// Renders the leaderboard for the given window. Returns `None` if the window
+    /// is the current induction cycle and no cycle has begun
+    pub async fn render(&self, ranking: Option<Ranking>, window: Window) -> Result<Option<Embed>> {
+        let now = crate::current_unix_time();
+        let since = match window {
+            Window::Day => now.saturating_sub(SECONDS_PER_DAY),
+            Window::Week => now.saturating_sub(7 * SECONDS_PER_DAY),
+            Window::Cycle => match self.database.open_induction_cycle().await? {
+                Some(cycle) => cycle.started,
+                None => return Ok(None)
+            },
+            Window::AllTime => 0
+        };
+        let title = match window {
+            Window::Day => "Leaderboard for the past day",
+            Window::Week => "Leaderboard for the past week",
+            Window::Cycle => "Leaderboard for the current induction cycle",
+            Window::AllTime => "Leaderboard of all time"
+        };
+        self.render_between(title, ranking, since, u64::MAX).await.map(Some)
+    }
+
+    pub async fn render_between(&self, title: &str, ranking: Option<Ranking>,
+                                since: u64, until: u64) -> Result<Embed> {
+        let ranking = ranking.unwrap_or_else(|| self.qualifying_messages());
+        let ranked = self.database.leaderboard(since, until, ranking, LEADERBOARD_SIZE).await?;
+        let embed = Embed::new(title).colour(LEADERBOARD_COLOUR);
+        if ranked.is_empty() {
+            return Ok(embed.footer("Nobody has been active"));
+        }
+        let unit = match ranking {
+            Ranking::QualifyingMessages { .. } => "qualifying messages",
+            Ranking::TotalScore => "points"
+        };
+        let embed = ranked.into_iter().enumerate().fold(embed, |embed, (rank, (user, score))| {
+            embed.field(format!("{}. {}", rank + 1, user.display_name()), format!("{} {}", score, unit))
+        });
+        Ok(embed.footer(format!("Ranked by {}", unit)))
+    }
+}
+
+#[derive(Debug)]
+pub struct LeaderboardCommand {
+    leaderboard: Leaderboard
+}
+
+impl LeaderboardCommand {
+    pub fn new(leaderboard: Leaderboard) -> Self {
+        Self {
+            leaderboard
+        }
+    }
+}
+
+#[async_trait]
+impl Command for LeaderboardCommand {
+    fn name(&self) -> &'static str {
+        "leaderboard"
+    }
+
+    fn usage(&self) -> &'static str {
+        "[messages|score] [day|week|cycle|all]"
+    }
+
+    fn help(&self) -> &'static str {
+        "Ranks users by qualifying messages or total score. Defaults to qualifying messages in the current cycle"
+    }
+
+    async fn execute(&self, _message: &IncomingMessage<'_>, arguments: &[String]) -> Result<Response> {
+        let (mut ranking, mut window) = (None, Window::Cycle);
+        for argument in arguments {
+            match argument.to_lowercase().as_str() {
+                "messages" => ranking = None,
+                "score" => ranking = Some(Ranking::TotalScore),
+                "day" => window = Window::Day,
+                "week" => window = Window::Week,
+                "cycle" => window = Window::Cycle,
+                "all" => window = Window::AllTime,
+                _ => return Ok(Response::private(format!("Unknown option: {}", argument)))
+            }
+        }
+        Ok(match self.leaderboard.render(ranking, window).await? {
+            Some(leaderboard) => Response::public_embed(leaderboard),
+            None => Response::public("The first induction cycle has not begun")
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::brain::Assessment;
+    use crate::brain::tokenizer::TokenCounts;
+    use crate::config::MessageRequirement;
+    use crate::database::{test_support, RecordedMessage, UserIdentifier};
+    use super::*;
+
+    fn leaderboard(database: Database) -> Leaderboard {
+        Leaderboard::new(Live::new(Induction {
+            message_requirements: vec![
+                MessageRequirement { message_count: 10, word_count: 5 },
+                MessageRequirement { message_count: 20, word_count: 3 }
+            ],
+            induction_cycle_days: 7
+        }), database)
+    }
+
+    async fn record(database: &Database, nickname: &str, score: u32) -> Result<()> {
+        let assessment = Assessment { tokens: TokenCounts::default(), score };
+        database.record_message(&RecordedMessage::new(UserIdentifier::IrcNickname(nickname), "", assessment)).await?;
+        Ok(())
+    }
+
+    fn fields(embed: &Embed) -> Vec<(&str, &str)> {
+        embed.fields.iter().map(|(name, value)| (name.as_str(), value.as_str())).collect()
+    }
+
+    #[async_std::test]
+    async fn empty_leaderboard() -> Result<()> {
+        let tempdir = tempfile::tempdir()?;
+        let database = test_support::sqlite_in(&tempdir).await?;
+        database.migrate().await?;
+        let leaderboard = leaderboard(database);
+
+        let embed = leaderboard.render(None, Window::AllTime).await?.unwrap();
+        assert!(embed.fields.is_empty());
+        assert_eq!(Some("Nobody has been active"), embed.footer.as_deref());
+        // Without an induction cycle, there is no current cycle to rank
+        assert_eq!(None, leaderboard.render(None, Window::Cycle).await?);
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn select_ranking() -> Result<()> {
+        let tempdir = tempfile::tempdir()?;
+        let database = test_support::sqlite_in(&tempdir).await?;
+        database.migrate().await?;
+        for _ in 0..3 {
+            record(&database, "chatty", 3).await?;
+        }
+        record(&database, "wordy", 20).await?;
+        record(&database, "terse", 1).await?;
+        let leaderboard = leaderboard(database);
+
+        // Qualifying messages meet the smallest word requirement
+        let by_messages = leaderboard.render(None, Window::AllTime).await?.unwrap();
+        assert_eq!(vec![
+            ("1. chatty", "3 qualifying messages"), ("2. wordy", "1 qualifying messages")
+        ], fields(&by_messages));
+        assert_eq!(Some("Ranked by qualifying messages"), by_messages.footer.as_deref());
+
+        let by_score = leaderboard.render(Some(Ranking::TotalScore), Window::AllTime).await?.unwrap();
+        assert_eq!(vec![
+            ("1. wordy", "20 points"), ("2. chatty", "9 points"), ("3. terse", "1 points")
+        ], fields(&by_score));
+        assert_eq!(Some("Ranked by points"), by_score.footer.as_deref());
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn bound_by_window() -> Result<()> {
+        let tempdir = tempfile::tempdir()?;
+        let database = test_support::sqlite_in(&tempdir).await?;
+        database.migrate().await?;
+        record(&database, "A248", 5).await?;
+        let now = crate::current_unix_time();
+        let leaderboard = leaderboard(database.clone());
+        let ranking = Some(Ranking::TotalScore);
+
+        assert_eq!(1, leaderboard.render(ranking, Window::Day).await?.unwrap().fields.len());
+        assert_eq!(1, leaderboard.render_between("Title", ranking, now - 60, now + 60).await?.fields.len());
+        // The window's end is exclusive
+        assert!(leaderboard.render_between("Title", ranking, 0, now - 60).await?.fields.is_empty());
+        assert!(leaderboard.render_between("Title", ranking, now + 60, u64::MAX).await?.fields.is_empty());
+
+        // Messages before the current cycle began are not ranked in it
+        database.begin_induction_cycle(now + 60).await?;
+        assert!(leaderboard.render(ranking, Window::Cycle).await?.unwrap().fields.is_empty());
+        Ok(())
+    }
+}
@@ -0,0 +1,160 @@
+/*
+ * faithful-servant-bot
+ * Copyright © 2022 Anand Beh
+ *
+ * faithful-servant-bot is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * faithful-servant-bot is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with faithful-servant-bot. If not, see <https://www.gnu.org/licenses/>
+ * and navigate to version 3 of the GNU General Public License.
+ */
+
+use async_trait::async_trait;
+use eyre::Result;
+use rand::Rng;
+use crate::brain::{IncomingMessage, Response};
+use crate::brain::commands::Command;
+use crate::database::{Database, Redemption, UserIdentifier};
+use crate::induction::Inductions;
+
+/// Characters used in link codes. Easily confused characters, such as 0 and O, are omitted
+const CODE_ALPHABET: &[u8] = b"ABCDEFGHJKLMNPQRSTUVWXYZ23456789";
+const CODE_LENGTH: usize = 8;
+const CODE_VALIDITY_SECONDS: u64 = 10 * 60;
+
+/// Links discord accounts and IRC nicknames belonging to the same person.
+/// The person obtains a one-time code on discord, then sends it to the bot over IRC
+#[derive(Clone, Debug)]
+pub struct AccountLinking {
+    database: Database,
+    inductions: Inductions
+}
+
+impl AccountLinking {
+    pub fn new(database: Database, inductions: Inductions) -> Self {
+        Self {
+            database,
+            inductions
+        }
+    }
+
+    /// Begins linking for the given discord user, yielding the code to send over IRC
+    pub async fn begin(&self, discord_id: u64) -> Result<String> {
+        let code = generate_code();
+        let expires = crate::current_unix_time() + CODE_VALIDITY_SECONDS;
+        self.database.create_link_code(discord_id, &code, expires).await?;
+        Ok(code)
+    }
+
+    /// Completes linking using a code sent by the given IRC nickname, whose owner must be
+    /// identified to services
+    pub async fn complete(&self, code: &str, irc_nickname: &str) -> Result<Redemption> {
+        let code = code.trim().to_uppercase();
+        let redemption = self.database.redeem_link_code(
+            &code, irc_nickname, crate::current_unix_time()
+        ).await?;
+        if let Redemption::Linked(linked) = &redemption {
+            log::info!("Linked IRC nickname {} to user #{}", irc_nickname, linked.user_id);
+            // The newly linked nickname needs the effects of induction too
+            if linked.inducted {
+                self.inductions.announce_granted(&[linked.user_id]).await?;
+            }
+        }
+        Ok(redemption)
+    }
+}
+
+/// Begins linking on discord, or completes it on IRC
+#[derive(Debug)]
+pub struct LinkCommand {
+    account_linking: AccountLinking
+}
+
+impl LinkCommand {
+    pub fn new(account_linking: AccountLinking) -> Self {
+        Self {
+            account_linking
+        }
+    }
+}
+
+#[async_trait]
+impl Command for LinkCommand {
+    fn name(&self) -> &'static str {
+        "link"
+    }
+
+    fn usage(&self) -> &'static str {
+        "[code]"
+    }
+
+    fn help(&self) -> &'static str {
+        "Links your discord account and IRC nickname. Run it on discord to obtain a code, \
+        then send the code to the bot by private message on IRC while identified to services"
+    }
+
+    async fn execute(&self, message: &IncomingMessage<'_>, arguments: &[String]) -> Result<Response> {
+        let response = match (message.sender, arguments.first()) {
+            (UserIdentifier::DiscordId(discord_id), None) => {
+                let code = self.account_linking.begin(discord_id).await?;
+                // The code must stay private, since anyone holding it could claim the account
+                format!(
+                    "To link your IRC nickname, identify to services on IRC, then send me `link {}` \
+                    by private message within {} minutes", code, CODE_VALIDITY_SECONDS / 60
+                )
+            }
+            (UserIdentifier::DiscordId(_), Some(_)) => {
+                String::from("Codes must be sent to me by private message on IRC")
+            }
+            (UserIdentifier::IrcNickname(_), None) => {
+                String::from("Use this command on discord to obtain a link code")
+            }
+            (UserIdentifier::IrcNickname(_), Some(_)) if !message.is_private => {
+                String::from("Link codes must be sent by private message. Please obtain a new code")
+            }
+            // Only the owner of a registered nickname may link it
+            (UserIdentifier::IrcNickname(irc_nickname), Some(_))
+                if !matches!(message.irc_account, Some(account) if account.eq_ignore_ascii_case(irc_nickname)) => {
+                String::from("Identify to services with the account registered to your nickname, \
+                    then send the code again")
+            }
+            (UserIdentifier::IrcNickname(irc_nickname), Some(code)) => {
+                match self.account_linking.complete(code, irc_nickname).await? {
+                    Redemption::Linked(_) => String::from("Your IRC nickname is now linked to your discord account"),
+                    Redemption::AlreadyLinked => {
+                        String::from("Your IRC nickname is already linked to another discord account")
+                    }
+                    Redemption::InvalidCode => String::from("That link code is invalid or has expired")
+                }
+            }
+        };
+        Ok(Response::private(response))
+    }
+}
+
+fn generate_code() -> String {
+    let mut rng = rand::thread_rng();
+    (0..CODE_LENGTH)
+        .map(|_| CODE_ALPHABET[rng.gen_range(0..CODE_ALPHABET.len())] as char)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn codes_use_alphabet() {
+        let code = generate_code();
+        assert_eq!(CODE_LENGTH, code.len());
+        assert!(code.bytes().all(|c| CODE_ALPHABET.contains(&c)));
+    }
+}
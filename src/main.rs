@@ -23,18 +23,42 @@ mod config;
 mod irc;
 mod discord;
 mod database;
+mod faq;
 mod brain;
+mod identity;
+mod induction;
+mod leaderboard;
+mod linking;
+mod reload;
+mod stats;
+mod supervisor;
+mod tracking;
 
-use std::sync::Arc;
-use std::sync::atomic::{AtomicBool, Ordering};
-use std::time::Duration;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+use async_std::channel::{self, Receiver, Sender};
 use async_std::path::PathBuf;
 use async_std::task::{self, JoinHandle};
 use eyre::Result;
-use futures::{StreamExt, future};
-use crate::database::Database;
+use futures::{FutureExt, StreamExt, future};
+use futures::future::Either;
+use crate::brain::Brain;
+use crate::brain::quality::QualityScorer;
+use crate::brain::commands::Commands;
+use crate::brain::conversation::Conversation;
+use crate::brain::dictionary::{Dictionaries, DictionariesCommand};
 use crate::discord::DiscordBot;
-use crate::irc::IrcBot;
+use crate::faq::{Faq, FaqCommand};
+use crate::identity::{IdentityFilter, SkippedCommand};
+use crate::induction::{InductCommand, InductionEngine, Inductions, RevokeCommand};
+use crate::irc::{BotChannels, IrcBot};
+use crate::leaderboard::{Leaderboard, LeaderboardCommand};
+use crate::linking::{AccountLinking, LinkCommand};
+use crate::reload::Reloader;
+use crate::stats::{StatsCommand, StatusCommand};
+use crate::supervisor::Supervisor;
+use crate::tracking::TrackingPolicy;
 
 fn main() -> core::result::Result<(), eyre::Report> {
     use std::env;
@@ -48,91 +72,264 @@ fn main() -> core::result::Result<(), eyre::Report> {
 }
 
 async fn async_main() -> Result<()> {
-    use crate::config::Config;
+    use crate::config::{Config, Live};
 
-    let Config { postgres_url, irc_server, discord_bot, induction } = Config::load(
-        &PathBuf::from("config.ron")
-    ).await?;
+    let check_config = std::env::args().any(|arg| arg == "--check-config");
+    let migrate_only = std::env::args().any(|arg| arg == "--migrate-only");
 
-    let database = Database::from(
-        sqlx::postgres::PgPool::connect_lazy(&postgres_url)?
-    );
-    database.create_schema().await?;
-    log::info!("Database is connected and ready");
+    let config_path = PathBuf::from("config.ron");
+    // Checking must not leave behind a default configuration
+    if check_config && !config_path.exists().await {
+        return Err(eyre::eyre!("There is no configuration file at {}", config_path.display()));
+    }
+    let config = Config::load(&config_path).await?;
+    if migrate_only {
+        // Migrating needs nothing but the database
+        config.check_database()?;
+    } else {
+        config.check()?;
+    }
+    if check_config {
+        println!("The configuration is valid");
+        return Ok(());
+    }
+    let running_config = config.clone();
+    let Config {
+        postgres_url: _, database, irc_server, discord_bot, induction, commands, tracking, quality, dictionaries,
+        conversation, faq, supervision
+    } = config;
+
+    let database = database::connect(&database).await?;
+    let schema_version = database.migrate().await?;
+    log::info!("Database is connected and ready at schema version {}", schema_version);
+    if migrate_only {
+        return Ok(());
+    }
 
+    let dictionaries = Arc::new(Dictionaries::load(dictionaries).await?);
     let shutdown_signal = Arc::new(ShutdownSignal::default());
+    let identity_filter = IdentityFilter::new(&tracking);
+    let tracking = TrackingPolicy::new(tracking);
+    let inductions = Inductions::new(database.clone());
+    let induction = Live::new(induction);
+    let bot_channels = BotChannels::new(irc_server.bot_channels.clone());
+    let reloader = Reloader::new(
+        config_path, running_config, tracking.clone(), identity_filter.clone(),
+        induction.clone(), bot_channels.clone(), dictionaries.clone()
+    );
+    let brain = {
+        let mut commands = Commands::new(commands.prefix);
+        commands.register(LinkCommand::new(AccountLinking::new(database.clone(), inductions.clone())));
+        commands.register(InductCommand::new(database.clone(), inductions.clone()));
+        commands.register(RevokeCommand::new(database.clone(), inductions.clone()));
+        commands.register(StatsCommand::new(induction.clone(), database.clone()));
+        commands.register(StatusCommand::new(induction.clone(), database.clone()));
+        commands.register(LeaderboardCommand::new(Leaderboard::new(induction.clone(), database.clone())));
+        commands.register(SkippedCommand::new(identity_filter.counters().clone()));
+        commands.register(DictionariesCommand::new(dictionaries.clone()));
+        let faq = Arc::new(Faq::new(faq, database.clone()));
+        commands.register(FaqCommand::new(faq.clone(), database.clone()));
+        let conversation = Conversation::new(conversation, database.clone());
+        Arc::new(Brain::new(commands, QualityScorer::new(quality), dictionaries, conversation, faq))
+    };
 
-    let irc_task = {
+    let drain_deadline = Duration::from_secs(supervision.drain_deadline_seconds);
+    let mut supervisor = Supervisor::new(supervision, shutdown_signal.clone());
+    {
         let database = database.clone();
+        let brain = brain.clone();
+        let tracking = tracking.clone();
+        let identity_filter = identity_filter.clone();
+        let inductions = inductions.clone();
         let shutdown_signal = shutdown_signal.clone();
-        task::spawn(async move {
-            let irc_bot = IrcBot::new(irc_server, database).await?;
-            irc_bot.start(shutdown_signal).await
-        })
-    };
-    let discord_task = {
-        let discord_bot = DiscordBot::new(discord_bot, database);
+        supervisor.supervise("IRC", move || {
+            let irc_bot = IrcBot::new(
+                irc_server.clone(), database.clone(), brain.clone(), tracking.clone(),
+                identity_filter.clone(), bot_channels.clone(), inductions.subscribe()
+            );
+            irc_bot.start(shutdown_signal.clone()).boxed()
+        });
+    }
+    {
+        let database = database.clone();
+        let inductions = inductions.clone();
         let shutdown_signal = shutdown_signal.clone();
-        task::spawn(async move {
-            discord_bot.start(shutdown_signal).await
-        })
-    };
-    await_shutdown(irc_task, discord_task, shutdown_signal).await
+        supervisor.supervise("discord", move || {
+            let discord_bot = DiscordBot::new(
+                discord_bot.clone(), database.clone(), brain.clone(), tracking.clone(),
+                identity_filter.clone(), inductions.subscribe()
+            );
+            discord_bot.start(shutdown_signal.clone()).boxed()
+        });
+    }
+    {
+        let shutdown_signal = shutdown_signal.clone();
+        supervisor.supervise("induction", move || {
+            let induction_engine = InductionEngine::new(induction.clone(), database.clone(), inductions.clone());
+            induction_engine.start(shutdown_signal.clone()).boxed()
+        });
+    }
+    await_shutdown(task::spawn(supervisor.run()), shutdown_signal, drain_deadline, reloader).await
+}
+
+/// The current time, in seconds since the unix epoch
+pub fn current_unix_time() -> u64 {
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("Failed to obtain duration since unix epoch")
+        .as_secs()
 }
 
 /*
 Shutdown logic
  */
 
-async fn await_shutdown(irc_task: JoinHandle<Result<()>>,
-                        discord_task: JoinHandle<Result<()>>,
-                        shutdown_signal: Arc<ShutdownSignal>) -> Result<()> {
+/// Waits for a signal, or for the supervisor to give up on a task, then stops every task.
+/// Tasks and in-flight writes are given until the drain deadline to finish, then abandoned.
+/// SIGHUP reloads the configuration instead
+async fn await_shutdown(supervision: JoinHandle<()>, shutdown_signal: Arc<ShutdownSignal>,
+                        drain_deadline: Duration, mut reloader: Reloader) -> Result<()> {
     use signal_hook_async_std::Signals;
     use signal_hook::consts::signal::*;
 
     let mut signals = Signals::new([SIGINT, SIGTERM, SIGHUP])?;
-    let _signal = signals.next().await;
-
-    shutdown_signal.commence_shutdown();
+    loop {
+        let signal = Box::pin(signals.next());
+        match future::select(signal, Box::pin(shutdown_signal.await_shutdown())).await {
+            Either::Left((Some(SIGHUP), _)) => {
+                log::info!("Reloading the configuration...");
+                if let Err(e) = reloader.reload().await {
+                    log::error!("Failed to reload the configuration: {}", e);
+                }
+            }
+            Either::Left(_) => {
+                shutdown_signal.commence_shutdown();
+                break;
+            }
+            Either::Right(_) => break
+        }
+    }
     log::info!("Initiating shutdown...");
 
-    let (irc_result, discord_result) = future::join(irc_task, discord_task).await;
-    if let Err(e) = irc_result {
-        log::error!("Error in IRC task: {}", e);
+    let deadline = Instant::now() + drain_deadline;
+    if async_std::future::timeout(drain_deadline, supervision).await.is_err() {
+        log::warn!("Forcing exit since tasks did not stop within {} seconds", drain_deadline.as_secs());
+        return Ok(());
     }
-    if let Err(e) = discord_result {
-        log::error!("Error in discord task: {}", e);
+    if !shutdown_signal.drain(deadline.saturating_duration_since(Instant::now())).await {
+        log::warn!("Forcing exit with {} message writes unfinished", shutdown_signal.writes_in_flight());
     }
     Ok(())
 }
 
+/// Tells every task when to shut down, and lets shutdown wait for in-flight writes
+#[derive(Debug)]
 pub struct ShutdownSignal {
-    shutdown: AtomicBool,
-    sleep_duration: Duration
+    /// Nothing is sent, but dropping the sender closes the channel, waking every waiting task
+    closer: Mutex<Option<Sender<()>>>,
+    closed: Receiver<()>,
+    writes_in_flight: AtomicUsize,
+    /// Notified whenever the last in-flight write finishes
+    writes_finished: (Sender<()>, Receiver<()>)
 }
 
 impl Default for ShutdownSignal {
     fn default() -> Self {
+        let (closer, closed) = channel::bounded(1);
         Self {
-            shutdown: AtomicBool::default(),
-            sleep_duration: Duration::from_secs(2)
+            closer: Mutex::new(Some(closer)),
+            closed,
+            writes_in_flight: AtomicUsize::new(0),
+            writes_finished: channel::unbounded()
         }
     }
 }
 
 impl ShutdownSignal {
     fn is_shutdown(&self) -> bool {
-        self.shutdown.load(Ordering::SeqCst)
+        self.closed.is_closed()
     }
 
     pub async fn await_shutdown(&self) {
-        while !self.is_shutdown() {
-            task::sleep(self.sleep_duration).await;
-        }
+        // Fails once the channel is closed
+        while self.closed.recv().await.is_ok() {}
     }
 
     fn commence_shutdown(&self) {
-        self.shutdown.store(true, Ordering::SeqCst)
+        self.closer.lock().unwrap().take();
+    }
+
+    /// Marks a write which shutdown should allow to finish
+    pub fn begin_write(self: &Arc<Self>) -> InFlightWrite {
+        self.writes_in_flight.fetch_add(1, Ordering::SeqCst);
+        InFlightWrite {
+            shutdown_signal: self.clone()
+        }
+    }
+
+    fn writes_in_flight(&self) -> usize {
+        self.writes_in_flight.load(Ordering::SeqCst)
+    }
+
+    /// Waits for in-flight writes to finish. Returns false if the deadline passes first
+    async fn drain(&self, deadline: Duration) -> bool {
+        let drained = async {
+            while self.writes_in_flight() > 0 {
+                let _ = self.writes_finished.1.recv().await;
+            }
+        };
+        async_std::future::timeout(deadline, drained).await.is_ok()
+    }
+}
+
+/// Held for the duration of a write
+pub struct InFlightWrite {
+    shutdown_signal: Arc<ShutdownSignal>
+}
+
+impl Drop for InFlightWrite {
+    fn drop(&mut self) {
+        if self.shutdown_signal.writes_in_flight.fetch_sub(1, Ordering::SeqCst) == 1 {
+            let _ = self.shutdown_signal.writes_finished.0.try_send(());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[async_std::test]
+    async fn wake_waiters_upon_shutdown() {
+        let shutdown_signal = Arc::new(ShutdownSignal::default());
+        let waiters = (0..3).map(|_| {
+            let shutdown_signal = shutdown_signal.clone();
+            task::spawn(async move { shutdown_signal.await_shutdown().await })
+        }).collect::<Vec<_>>();
+        assert!(!shutdown_signal.is_shutdown());
+        shutdown_signal.commence_shutdown();
+        let woken = async_std::future::timeout(Duration::from_secs(1), future::join_all(waiters)).await;
+        assert!(woken.is_ok());
+        assert!(shutdown_signal.is_shutdown());
+    }
+
+    #[async_std::test]
+    async fn drain_writes_until_deadline() {
+        let shutdown_signal = Arc::new(ShutdownSignal::default());
+        assert!(shutdown_signal.drain(Duration::ZERO).await);
+
+        let write = shutdown_signal.begin_write();
+        let stuck = shutdown_signal.begin_write();
+        task::spawn(async move {
+            task::sleep(Duration::from_millis(50)).await;
+            drop(write);
+        });
+        assert!(!shutdown_signal.drain(Duration::from_millis(200)).await);
+        assert_eq!(1, shutdown_signal.writes_in_flight());
+        drop(stuck);
+        assert!(shutdown_signal.drain(Duration::from_millis(200)).await);
     }
 }
 
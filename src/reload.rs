@@ -0,0 +1,179 @@
+/*
+ * faithful-servant-bot
+ * Copyright © 2022 Anand Beh
+ *
+ * faithful-servant-bot is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * faithful-servant-bot is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with faithful-servant-bot. If not, see <https://www.gnu.org/licenses/>
+ * and navigate to version 3 of the GNU General Public License.
+ */
+
+use std::sync::Arc;
+use async_std::path::PathBuf;
+use eyre::Result;
+use crate::brain::dictionary::Dictionaries;
+use crate::config::{Config, Induction, Live};
+use crate::identity::IdentityFilter;
+use crate::irc::BotChannels;
+use crate::tracking::TrackingPolicy;
+
+/// Applies changes to the configuration file while the bot runs. Settings which
+/// cannot change while running are reported instead
+pub struct Reloader {
+    path: PathBuf,
+    /// The configuration in effect
+    running: Config,
+    tracking: TrackingPolicy,
+    identity_filter: IdentityFilter,
+    induction: Live<Induction>,
+    bot_channels: BotChannels,
+    dictionaries: Arc<Dictionaries>
+}
+
+impl Reloader {
+    pub fn new(path: PathBuf, running: Config, tracking: TrackingPolicy, identity_filter: IdentityFilter,
+               induction: Live<Induction>, bot_channels: BotChannels, dictionaries: Arc<Dictionaries>) -> Self {
+        Self {
+            path,
+            running,
+            tracking,
+            identity_filter,
+            induction,
+            bot_channels,
+            dictionaries
+        }
+    }
+
+    pub async fn reload(&mut self) -> Result<()> {
+        let config = Config::load(&self.path).await?;
+        // Nothing is applied from a configuration with any problem
+        config.check()?;
+        let changes = compare(&self.running, &config);
+        if changes == Changes::default() {
+            log::info!("The configuration is unchanged");
+            return Ok(());
+        }
+        if changes.bot_channels {
+            let channels = config.irc_server.bot_channels.clone();
+            let change = self.bot_channels.update(channels.clone());
+            log::info!("Joining IRC channels [{}] and parting [{}]",
+                       change.joined.join(", "), change.parted.join(", "));
+            self.running.irc_server.bot_channels = channels;
+        }
+        if changes.tracking {
+            self.tracking.update(config.tracking.clone());
+            self.identity_filter.update(&config.tracking);
+            log::info!("Applied the new tracking settings and ignore lists");
+            self.running.tracking = config.tracking.clone();
+        }
+        if changes.message_requirements {
+            let requirements = config.induction.message_requirements.clone();
+            self.induction.set(Induction {
+                message_requirements: requirements.clone(),
+                ..Induction::clone(&self.induction.get())
+            });
+            log::info!("Applied the new message requirements, which decide the current induction cycle");
+            self.running.induction.message_requirements = requirements;
+        }
+        if changes.dictionaries {
+            // Unlike the other settings, dictionaries depend on files which may be unreadable
+            match self.dictionaries.reload_with(config.dictionaries.clone()).await {
+                Ok(()) => {
+                    log::info!("Applied the new dictionaries");
+                    self.running.dictionaries = config.dictionaries.clone();
+                }
+                Err(e) => log::error!("Failed to load the new dictionaries, so the current ones remain: {}", e)
+            }
+        }
+        for section in &changes.needing_restart {
+            log::warn!("Changes to `{}` take effect only after a restart", section);
+        }
+        Ok(())
+    }
+}
+
+/// How a new configuration differs from the running one
+#[derive(Debug, Default, PartialEq, Eq)]
+struct Changes {
+    bot_channels: bool,
+    tracking: bool,
+    message_requirements: bool,
+    dictionaries: bool,
+    /// The sections whose other changes take effect only after a restart
+    needing_restart: Vec<&'static str>
+}
+
+fn compare(running: &Config, new: &Config) -> Changes {
+    // With the settings which change while running made equal, whatever still differs
+    // needs a restart
+    let mut aligned = new.clone();
+    aligned.irc_server.bot_channels = running.irc_server.bot_channels.clone();
+    aligned.tracking = running.tracking.clone();
+    aligned.induction.message_requirements = running.induction.message_requirements.clone();
+    aligned.dictionaries = running.dictionaries.clone();
+
+    // Destructured so that new sections cannot be overlooked
+    let Config {
+        postgres_url: _, database, irc_server, discord_bot, induction, commands, tracking, quality, dictionaries,
+        conversation, faq, supervision
+    } = &aligned;
+    let sections = [
+        ("database", *database != running.database),
+        ("irc_server", *irc_server != running.irc_server),
+        ("discord_bot", *discord_bot != running.discord_bot),
+        ("induction", *induction != running.induction),
+        ("commands", *commands != running.commands),
+        ("tracking", *tracking != running.tracking),
+        ("quality", *quality != running.quality),
+        ("dictionaries", *dictionaries != running.dictionaries),
+        ("conversation", *conversation != running.conversation),
+        ("faq", *faq != running.faq),
+        ("supervision", *supervision != running.supervision)
+    ];
+    Changes {
+        bot_channels: new.irc_server.bot_channels != running.irc_server.bot_channels,
+        tracking: new.tracking != running.tracking,
+        message_requirements: new.induction.message_requirements != running.induction.message_requirements,
+        dictionaries: new.dictionaries != running.dictionaries,
+        needing_restart: sections.iter().filter(|(_, changed)| *changed).map(|(section, _)| *section).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::MessageRequirement;
+    use super::*;
+
+    #[test]
+    fn separate_live_changes_from_restarts() {
+        let running = Config::default();
+        assert_eq!(Changes::default(), compare(&running, &running));
+
+        let mut new = running.clone();
+        new.irc_server.bot_channels.push(String::from("#new"));
+        new.tracking.ignored_irc_nicknames.push(String::from("Spammer"));
+        new.induction.message_requirements.push(MessageRequirement { message_count: 5, word_count: 3 });
+        new.dictionaries.allowed_words.push(String::from("yeet"));
+        assert_eq!(Changes {
+            bot_channels: true,
+            tracking: true,
+            message_requirements: true,
+            dictionaries: true,
+            needing_restart: vec![]
+        }, compare(&running, &new));
+
+        new.irc_server.port = 6697;
+        new.induction.induction_cycle_days = 14;
+        new.faq.cooldown_seconds = 1;
+        assert_eq!(vec!["irc_server", "induction", "faq"], compare(&running, &new).needing_restart);
+    }
+}
@@ -0,0 +1,200 @@
+/*
+ * faithful-servant-bot
+ * Copyright © 2022 Anand Beh
+ *
+ * faithful-servant-bot is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * faithful-servant-bot is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with faithful-servant-bot. If not, see <https://www.gnu.org/licenses/>
+ * and navigate to version 3 of the GNU General Public License.
+ */
+
+use async_trait::async_trait;
+use eyre::Result;
+use crate::brain::{Embed, IncomingMessage, Response};
+use crate::brain::commands::Command;
+use crate::config::{Induction, Live};
+use crate::database::{Database, UserIdentifier};
+use crate::induction::SECONDS_PER_DAY;
+
+/// Green
+const STATS_COLOUR: u32 = 0x2ECC71;
+
+/// Reports the caller's activity toward induction
+#[derive(Debug)]
+pub struct StatsCommand {
+    config: Live<Induction>,
+    database: Database
+}
+
+impl StatsCommand {
+    pub fn new(config: Live<Induction>, database: Database) -> Self {
+        Self {
+            config,
+            database
+        }
+    }
+}
+
+#[async_trait]
+impl Command for StatsCommand {
+    fn name(&self) -> &'static str {
+        "stats"
+    }
+
+    fn help(&self) -> &'static str {
+        "Shows your progress toward induction in the current cycle"
+    }
+
+    async fn execute(&self, message: &IncomingMessage<'_>, _arguments: &[String]) -> Result<Response> {
+        let user_id = match self.database.find_user(message.sender).await? {
+            Some(user_id) => user_id,
+            None => return Ok(not_recorded(message))
+        };
+        let total = self.database.count_messages(user_id, 0, 0).await?;
+        let embed = Embed::new(format!("Stats for {}", message.sender_name))
+            .colour(STATS_COLOUR)
+            .field("Messages in total", total.to_string());
+        let embed = induction_status(&self.config, &self.database, user_id, message.sender, embed).await?;
+        Ok(Response::public_embed(embed))
+    }
+}
+
+/// Reports whether the caller is inducted, and their progress in the current cycle
+#[derive(Debug)]
+pub struct StatusCommand {
+    config: Live<Induction>,
+    database: Database
+}
+
+impl StatusCommand {
+    pub fn new(config: Live<Induction>, database: Database) -> Self {
+        Self {
+            config,
+            database
+        }
+    }
+}
+
+#[async_trait]
+impl Command for StatusCommand {
+    fn name(&self) -> &'static str {
+        "status"
+    }
+
+    fn help(&self) -> &'static str {
+        "Shows whether you are inducted, and your progress in the current cycle"
+    }
+
+    async fn execute(&self, message: &IncomingMessage<'_>, _arguments: &[String]) -> Result<Response> {
+        let user_id = match self.database.find_user(message.sender).await? {
+            Some(user_id) => user_id,
+            None => return Ok(not_recorded(message))
+        };
+        let embed = Embed::new(format!("Induction status for {}", message.sender_name)).colour(STATS_COLOUR);
+        let embed = induction_status(&self.config, &self.database, user_id, message.sender, embed).await?;
+        Ok(Response::public_embed(embed))
+    }
+}
+
+fn not_recorded(message: &IncomingMessage<'_>) -> Response {
+    Response::public(format!("{}: I have not recorded any messages from you yet", message.sender_name))
+}
+
+/// Adds whether the user is inducted, their progress toward each message requirement in the
+/// current cycle, and when the cycle ends
+async fn induction_status(config: &Live<Induction>, database: &Database, user_id: i64,
+                          sender: UserIdentifier<'_>, embed: Embed) -> Result<Embed> {
+    let inducted = database.is_inducted(sender).await?;
+    let mut embed = embed.field("Inducted", if inducted { "Yes" } else { "No" });
+
+    match database.open_induction_cycle().await? {
+        Some(cycle) => {
+            let config = config.get();
+            for requirement in &config.message_requirements {
+                let count = database.count_messages(
+                    user_id, cycle.started, requirement.word_count as u32
+                ).await?;
+                embed = embed.field(
+                    format!("Messages of {}+ words this cycle", requirement.word_count),
+                    format!("{}/{}", count, requirement.message_count)
+                );
+            }
+            let ends = cycle.started + crate::induction::cycle_length(&config)?;
+            let remaining = ends.saturating_sub(crate::current_unix_time());
+            embed = embed.footer(format!("The cycle ends in {}", describe_days(remaining)));
+        }
+        None => embed = embed.footer("The first induction cycle has not begun")
+    }
+    Ok(embed)
+}
+
+/// Describes a duration in whole days, rounding up
+fn describe_days(seconds: u64) -> String {
+    match seconds.div_ceil(SECONDS_PER_DAY) {
+        1 => String::from("1 day"),
+        days => format!("{} days", days)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::brain::Assessment;
+    use crate::brain::commands::Permission;
+    use crate::brain::tokenizer::TokenCounts;
+    use crate::config::MessageRequirement;
+    use crate::database::{test_support, RecordedMessage};
+    use super::*;
+
+    #[test]
+    fn days_round_up() {
+        assert_eq!("0 days", describe_days(0));
+        assert_eq!("1 day", describe_days(1));
+        assert_eq!("1 day", describe_days(SECONDS_PER_DAY));
+        assert_eq!("2 days", describe_days(SECONDS_PER_DAY + 1));
+    }
+
+    #[async_std::test]
+    async fn report_induction_status() -> Result<()> {
+        let tempdir = tempfile::tempdir()?;
+        let database = test_support::sqlite_in(&tempdir).await?;
+        database.migrate().await?;
+        let config = Live::new(Induction {
+            message_requirements: vec![MessageRequirement { message_count: 10, word_count: 3 }],
+            induction_cycle_days: 7
+        });
+        let status = StatusCommand::new(config, database.clone());
+        let sender = UserIdentifier::IrcNickname("A248");
+        let message = IncomingMessage {
+            content: "!status",
+            sender,
+            sender_name: "A248",
+            channel: None,
+            own_names: &[],
+            permission: Permission::Member,
+            is_private: true,
+            irc_account: None
+        };
+        assert_eq!(not_recorded(&message), status.execute(&message, &[]).await?);
+
+        database.begin_induction_cycle(crate::current_unix_time() - 60).await?;
+        let assessment = Assessment { tokens: TokenCounts::default(), score: 4 };
+        database.record_message(&RecordedMessage::new(sender, "one two three four", assessment)).await?;
+        let embed = status.execute(&message, &[]).await?.embed.unwrap();
+        assert_eq!("Induction status for A248", embed.title);
+        assert_eq!(vec![
+            (String::from("Inducted"), String::from("No")),
+            (String::from("Messages of 3+ words this cycle"), String::from("1/10"))
+        ], embed.fields);
+        assert_eq!(Some("The cycle ends in 7 days"), embed.footer.as_deref());
+        Ok(())
+    }
+}
@@ -0,0 +1,194 @@
+/*
+ * faithful-servant-bot
+ * Copyright © 2022 Anand Beh
+ *
+ * faithful-servant-bot is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * faithful-servant-bot is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with faithful-servant-bot. If not, see <https://www.gnu.org/licenses/>
+ * and navigate to version 3 of the GNU General Public License.
+ */
+
+use std::collections::VecDeque;
+use std::future::Future;
+use std::panic::AssertUnwindSafe;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use async_std::task;
+use eyre::Result;
+use futures::{FutureExt, StreamExt};
+use futures::future::{self, BoxFuture, Either};
+use futures::stream::FuturesUnordered;
+use crate::config::{RestartPolicy, Supervision};
+use crate::ShutdownSignal;
+
+/// Starts a fresh instance of a task each time it is called
+pub type TaskFactory = Box<dyn FnMut() -> BoxFuture<'static, Result<()>> + Send>;
+
+struct SupervisedTask {
+    name: &'static str,
+    factory: TaskFactory,
+    restarts: Restarts
+}
+
+/// Runs the bot's long-lived tasks, restarting those which stop before shutdown.
+/// If a task cannot be kept running, the whole bot is shut down
+pub struct Supervisor {
+    config: Supervision,
+    shutdown_signal: Arc<ShutdownSignal>,
+    tasks: Vec<SupervisedTask>
+}
+
+impl Supervisor {
+    pub fn new(config: Supervision, shutdown_signal: Arc<ShutdownSignal>) -> Self {
+        Self {
+            config,
+            shutdown_signal,
+            tasks: Vec::new()
+        }
+    }
+
+    pub fn supervise<F>(&mut self, name: &'static str, factory: F)
+        where F: FnMut() -> BoxFuture<'static, Result<()>> + Send + 'static {
+        self.tasks.push(SupervisedTask {
+            name,
+            factory: Box::new(factory),
+            restarts: Restarts::default()
+        });
+    }
+
+    /// Runs every task until all of them have stopped following shutdown
+    pub async fn run(mut self) {
+        let restart_delay = Duration::from_secs(self.config.restart_delay_seconds);
+        let restart_window = Duration::from_secs(self.config.restart_window_seconds);
+
+        let mut running = FuturesUnordered::new();
+        for index in 0..self.tasks.len() {
+            running.push(self.spawn(index, Duration::ZERO));
+        }
+        while let Some((index, result)) = running.next().await {
+            let task = &mut self.tasks[index];
+            let shutting_down = self.shutdown_signal.is_shutdown();
+            let failed = match result {
+                Ok(()) if shutting_down => continue,
+                Ok(()) => {
+                    log::warn!("The {} task stopped unexpectedly", task.name);
+                    false
+                }
+                Err(e) => {
+                    log::error!("Error in {} task: {:?}", task.name, e);
+                    true
+                }
+            };
+            if shutting_down {
+                continue;
+            }
+            let restart = match self.config.restart {
+                RestartPolicy::Always => true,
+                RestartPolicy::OnFailure => failed,
+                RestartPolicy::Never => false
+            };
+            if !restart {
+                if failed {
+                    log::error!("Shutting down since the {} task failed", task.name);
+                    self.shutdown_signal.commence_shutdown();
+                }
+                continue;
+            }
+            let recent_restarts = task.restarts.record(Instant::now(), restart_window);
+            if recent_restarts > self.config.max_restarts {
+                log::error!("Shutting down since the {} task stopped {} times within {} seconds",
+                            task.name, recent_restarts, restart_window.as_secs());
+                self.shutdown_signal.commence_shutdown();
+                continue;
+            }
+            log::info!("Restarting the {} task in {} seconds", task.name, restart_delay.as_secs());
+            running.push(self.spawn(index, restart_delay));
+        }
+    }
+
+    /// Starts the task after the delay, unless shutdown comes first. Panics are reported as errors
+    fn spawn(&mut self, index: usize, delay: Duration) -> impl Future<Output = (usize, Result<()>)> {
+        let task = &mut self.tasks[index];
+        let name = task.name;
+        let instance = (task.factory)();
+        let shutdown_signal = self.shutdown_signal.clone();
+        let handle = task::spawn(async move {
+            let sleep = Box::pin(task::sleep(delay));
+            if let Either::Right(_) = future::select(sleep, Box::pin(shutdown_signal.await_shutdown())).await {
+                return Ok(());
+            }
+            match AssertUnwindSafe(instance).catch_unwind().await {
+                Ok(result) => result,
+                Err(_) => Err(eyre::eyre!("The {} task panicked", name))
+            }
+        });
+        async move { (index, handle.await) }
+    }
+}
+
+/// When a task was recently restarted
+#[derive(Debug, Default)]
+struct Restarts {
+    times: VecDeque<Instant>
+}
+
+impl Restarts {
+    /// Records a restart, yielding how many restarts fall within the window
+    fn record(&mut self, now: Instant, window: Duration) -> u32 {
+        while matches!(self.times.front(), Some(time) if now.duration_since(*time) >= window) {
+            self.times.pop_front();
+        }
+        self.times.push_back(now);
+        self.times.len() as u32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use super::*;
+
+    #[test]
+    fn count_restarts_within_window() {
+        let mut restarts = Restarts::default();
+        let start = Instant::now();
+        let window = Duration::from_secs(60);
+        assert_eq!(1, restarts.record(start, window));
+        assert_eq!(2, restarts.record(start + Duration::from_secs(30), window));
+        assert_eq!(2, restarts.record(start + Duration::from_secs(70), window));
+        assert_eq!(1, restarts.record(start + Duration::from_secs(200), window));
+    }
+
+    #[async_std::test]
+    async fn escalate_repeated_failures() {
+        let shutdown_signal = Arc::new(ShutdownSignal::default());
+        let mut supervisor = Supervisor::new(Supervision {
+            restart: RestartPolicy::OnFailure,
+            restart_delay_seconds: 0,
+            max_restarts: 2,
+            restart_window_seconds: 60,
+            drain_deadline_seconds: 0
+        }, shutdown_signal.clone());
+        let starts = Arc::new(AtomicU32::new(0));
+        {
+            let starts = starts.clone();
+            supervisor.supervise("failing", move || {
+                starts.fetch_add(1, Ordering::SeqCst);
+                async { Err(eyre::eyre!("failure")) }.boxed()
+            });
+        }
+        supervisor.run().await;
+        assert!(shutdown_signal.is_shutdown());
+        // The first start, followed by two restarts
+        assert_eq!(3, starts.load(Ordering::SeqCst));
+    }
+}
@@ -0,0 +1,137 @@
+/*
+ * faithful-servant-bot
+ * Copyright © 2022 Anand Beh
+ *
+ * faithful-servant-bot is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * faithful-servant-bot is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with faithful-servant-bot. If not, see <https://www.gnu.org/licenses/>
+ * and navigate to version 3 of the GNU General Public License.
+ */
+
+use crate::config::{Live, Tracking};
+
+/// What becomes of a message once it is seen
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Disposition {
+    /// Recorded, counting toward induction
+    Counted,
+    /// Recorded apart from the messages which count
+    Uncounted,
+    /// Not recorded at all
+    Ignored
+}
+
+impl Disposition {
+    pub fn is_recorded(&self) -> bool {
+        *self != Self::Ignored
+    }
+}
+
+/// Decides which channels' messages count toward induction. Clones share their settings
+#[derive(Clone, Debug)]
+pub struct TrackingPolicy {
+    config: Live<Tracking>
+}
+
+impl TrackingPolicy {
+    pub fn new(config: Tracking) -> Self {
+        Self {
+            config: Live::new(config)
+        }
+    }
+
+    pub fn update(&self, config: Tracking) {
+        self.config.set(config);
+    }
+
+    /// The disposition of an IRC message sent to the given channel, or privately if `None`
+    pub fn irc_message(&self, channel: Option<&str>) -> Disposition {
+        let config = self.config.get();
+        let counts = match channel {
+            // IRC channel names are case insensitive
+            Some(channel) => {
+                let is_listed = |channels: &[String]| {
+                    channels.iter().any(|listed| listed.eq_ignore_ascii_case(channel))
+                };
+                !is_listed(&config.irc_excluded_channels)
+                    && (config.irc_channels.is_empty() || is_listed(&config.irc_channels))
+            }
+            None => config.count_private_messages
+        };
+        disposition(&config, counts)
+    }
+
+    /// The disposition of a discord message sent in the given guild and channel.
+    /// Direct messages have no guild
+    pub fn discord_message(&self, guild_id: Option<u64>, channel_id: u64) -> Disposition {
+        let config = self.config.get();
+        let counts = match guild_id {
+            Some(guild_id) => {
+                (config.discord_guild_ids.is_empty() || config.discord_guild_ids.contains(&guild_id))
+                    && !config.discord_excluded_channel_ids.contains(&channel_id)
+                    && (config.discord_channel_ids.is_empty() || config.discord_channel_ids.contains(&channel_id))
+            }
+            None => config.count_private_messages
+        };
+        disposition(&config, counts)
+    }
+}
+
+fn disposition(config: &Tracking, counts: bool) -> Disposition {
+    match (counts, config.record_excluded_messages) {
+        (true, _) => Disposition::Counted,
+        (false, true) => Disposition::Uncounted,
+        (false, false) => Disposition::Ignored
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn private_messages_are_excluded_by_default() {
+        let policy = TrackingPolicy::new(Tracking::default());
+        assert_eq!(Disposition::Ignored, policy.irc_message(None));
+        assert_eq!(Disposition::Ignored, policy.discord_message(None, 1));
+        assert_eq!(Disposition::Counted, policy.irc_message(Some("#general")));
+        assert_eq!(Disposition::Counted, policy.discord_message(Some(1), 2));
+    }
+
+    #[test]
+    fn irc_channel_lists() {
+        let policy = TrackingPolicy::new(Tracking {
+            irc_channels: vec![String::from("#General"), String::from("#offtopic")],
+            irc_excluded_channels: vec![String::from("#offtopic")],
+            record_excluded_messages: true,
+            ..Tracking::default()
+        });
+        assert_eq!(Disposition::Counted, policy.irc_message(Some("#general")));
+        assert_eq!(Disposition::Uncounted, policy.irc_message(Some("#offtopic")));
+        assert_eq!(Disposition::Uncounted, policy.irc_message(Some("#elsewhere")));
+        assert_eq!(Disposition::Uncounted, policy.irc_message(None));
+    }
+
+    #[test]
+    fn discord_guild_and_channel_lists() {
+        let policy = TrackingPolicy::new(Tracking {
+            discord_guild_ids: vec![10],
+            discord_excluded_channel_ids: vec![2],
+            count_private_messages: true,
+            ..Tracking::default()
+        });
+        assert_eq!(Disposition::Counted, policy.discord_message(Some(10), 1));
+        assert_eq!(Disposition::Ignored, policy.discord_message(Some(10), 2));
+        assert_eq!(Disposition::Ignored, policy.discord_message(Some(11), 1));
+        assert_eq!(Disposition::Counted, policy.discord_message(None, 3));
+    }
+}